@@ -2,11 +2,11 @@ use criterion::{black_box, criterion_group, criterion_main, Criterion};
 use oxide::{chess_move::MoveFlag, *};
 
 fn bench_move(c: &mut Criterion) {
-    let move_ = chess_move::Move {
-        from: definitions::Square::E2,
-        to: definitions::Square::E4,
-        flag: MoveFlag::PawnDoubleMove,
-    };
+    let move_ = chess_move::Move::new(
+        definitions::Square::E2,
+        definitions::Square::E4,
+        MoveFlag::PawnDoubleMove,
+    );
 
     c.bench_function("Move::is_promotion", |b| {
         b.iter(|| black_box(move_).is_promotion())
@@ -17,6 +17,21 @@ fn bench_move(c: &mut Criterion) {
     c.bench_function("Move::is_castle", |b| {
         b.iter(|| black_box(move_).is_castle())
     });
+    c.bench_function("Move::new", |b| {
+        b.iter(|| {
+            chess_move::Move::new(
+                black_box(definitions::Square::E2),
+                black_box(definitions::Square::E4),
+                black_box(MoveFlag::PawnDoubleMove),
+            )
+        })
+    });
+    c.bench_function("Move::from_to_flag", |b| {
+        b.iter(|| {
+            let m = black_box(move_);
+            (m.from(), m.to(), m.flag())
+        })
+    });
 }
 
 criterion_group!(benches, bench_move);
@@ -9,6 +9,17 @@ fn bench_perft(c: &mut Criterion) {
     c.bench_with_input(BenchmarkId::new("perft", depth), &depth, |b, &depth| {
         b.iter(|| perft::perft(&board, depth))
     });
+
+    c.bench_with_input(
+        BenchmarkId::new("perft_hashed", depth),
+        &depth,
+        |b, &depth| {
+            b.iter(|| {
+                let table = perft::PerftTable::new(1 << 16);
+                perft::perft_hashed(&board, depth, &table)
+            })
+        },
+    );
 }
 
 criterion_group! {
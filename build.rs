@@ -0,0 +1,37 @@
+use std::path::{Path, PathBuf};
+
+// Copy the default network into the build directory so it can be embedded with
+// `include_bytes!`. The network is taken from the OXIDE_NET environment
+// variable, or nets/default.nnue. Without either an empty file is embedded,
+// and the engine falls back to the hand crafted evaluation.
+fn main() {
+    println!("cargo:rerun-if-env-changed=OXIDE_NET");
+
+    let out_dir = PathBuf::from(std::env::var_os("OUT_DIR").unwrap());
+    let target = out_dir.join("default.nnue");
+
+    if let Some(path) = std::env::var_os("OXIDE_NET") {
+        let source = PathBuf::from(path);
+        if !source.exists() {
+            panic!(
+                "OXIDE_NET is set to {}, which does not exist",
+                source.display()
+            );
+        }
+        println!("cargo:rerun-if-changed={}", source.display());
+        std::fs::copy(&source, &target).expect("Could not copy the network");
+        return;
+    }
+
+    let nets = Path::new("nets");
+    let source = nets.join("default.nnue");
+    if source.exists() {
+        println!("cargo:rerun-if-changed={}", source.display());
+        std::fs::copy(&source, &target).expect("Could not copy the default network");
+    } else {
+        // watching a missing file would rebuild every time, so watch for the
+        // network being added instead
+        println!("cargo:rerun-if-changed={}", nets.display());
+        std::fs::write(&target, []).unwrap();
+    }
+}
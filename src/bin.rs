@@ -1,3 +1,383 @@
+use oxide::bench;
+use oxide::board::Board;
+use oxide::evaluation;
+use oxide::fen;
+use oxide::mate_search::{self, MateResult};
+use oxide::nnue::{self, Activation, Network};
+use oxide::nnue_trainer::{self, Trainer, TrainerConfig};
+use oxide::pawns::PawnHashTable;
+use oxide::perft::{self, DivideMismatch, PerftTable};
+use oxide::training_data;
+use oxide::tuner::{self, Tuner};
+
+use std::fs::File;
+use std::io::{BufRead, BufReader, BufWriter};
+use std::time::Instant;
+
 fn main() {
-    println!("Hello, world!");
+    let args: Vec<String> = std::env::args().collect();
+
+    match args.get(1).map(String::as_str) {
+        Some("bench") => bench(&args[2..]),
+        Some("eval") => eval(&args[2..]),
+        Some("mate") => mate(&args[2..]),
+        Some("perft") => perft_command(&args[2..]),
+        Some("tune") => tune(&args[2..]),
+        Some("train") => train(&args[2..]),
+        Some("pack") => convert(&args[2..], true),
+        Some("unpack") => convert(&args[2..], false),
+        Some("help" | "--help" | "-h") => println!("{USAGE}"),
+        _ => fail(USAGE),
+    }
+}
+
+const USAGE: &str = "Usage: oxide <command> [arguments]
+
+Commands:
+    bench [EvalFile]                                       evaluation signature
+    eval [fen]                                             evaluation trace
+    mate <moves> [fen]                                     find a forced mate
+    perft divide <depth> [fen]                             move counts per move
+    perft suite <epd file> [max depth]                     check perft counts
+    perft diff <depth> [fen]                               find a wrong move count
+    tune <positions> [epochs] [learning rate]              tune the evaluation
+    train <packed data> <network> [epochs] [crelu|screlu]  train a network
+    pack <text> <packed>                                   pack training data
+    unpack <packed> <text>                                 unpack training data
+    help                                                   show this message";
+
+// Report an error, such as bad arguments, and exit
+fn fail(message: impl std::fmt::Display) -> ! {
+    eprintln!("{message}");
+    std::process::exit(2);
+}
+
+const PERFT_USAGE: &str = "Usage:
+    oxide perft divide <depth> [fen]
+    oxide perft suite <epd file> [max depth]
+    oxide perft diff <depth> [fen]";
+
+const START_FEN: &str = "rnbqkbnr/pppppppp/8/8/8/8/PPPPPPPP/RNBQKBNR w KQkq - 0 1";
+
+fn perft_command(args: &[String]) {
+    let depth = || -> u32 {
+        args.get(1)
+            .and_then(|d| d.parse().ok())
+            .filter(|&d| d > 0)
+            .unwrap_or_else(|| fail(PERFT_USAGE))
+    };
+    let board = || board_from_args(args.get(2..).unwrap_or_default());
+
+    match args.first().map(String::as_str) {
+        Some("divide") => perft_divide(&board(), depth()),
+        Some("suite") => perft_suite(&args[1..]),
+        Some("diff") => perft_diff(board(), depth()),
+        _ => fail(PERFT_USAGE),
+    }
+}
+
+fn nps(nodes: u64, start: Instant) -> u64 {
+    (nodes as f64 / start.elapsed().as_secs_f64()) as u64
+}
+
+// oxide perft divide <depth> [fen]
+fn perft_divide(board: &Board, depth: u32) {
+    let start = Instant::now();
+    let divide = perft::perft_divide(board, depth);
+    for (m, nodes) in &divide {
+        println!("{m}: {nodes}");
+    }
+
+    let total = divide.iter().map(|(_, n)| n).sum();
+    println!(
+        "\nNodes searched: {total} in {:?} ({} nps)",
+        start.elapsed(),
+        nps(total, start)
+    );
+}
+
+// oxide perft suite <epd file> [max depth]
+fn perft_suite(args: &[String]) {
+    let path = args.first().unwrap_or_else(|| fail(PERFT_USAGE));
+    let max_depth: u32 = parse_arg(args.get(1), u32::MAX, PERFT_USAGE);
+    let file = open(path);
+
+    let threads = std::thread::available_parallelism().map_or(1, |n| n.get());
+    let table = PerftTable::new(1 << 22);
+    let start = Instant::now();
+    let (mut nodes, mut failed) = (0, 0);
+
+    for (i, line) in BufReader::new(file).lines().enumerate() {
+        let line = line.unwrap_or_else(|e| fail(format!("Could not read {path}: {e}")));
+        if line.trim().is_empty() {
+            continue;
+        }
+        let entry = perft::parse_suite_line(&line)
+            .unwrap_or_else(|e| fail(format!("{path} line {}: {e}", i + 1)));
+
+        for &(depth, expected) in entry.counts.iter().filter(|(d, _)| *d <= max_depth) {
+            let count = perft::perft_parallel(&entry.board, depth, threads, Some(&table));
+            nodes += count;
+            if count == expected {
+                println!("PASS line {} depth {depth}: {count}", i + 1);
+            } else {
+                failed += 1;
+                println!(
+                    "FAIL line {} depth {depth}: expected {expected}, got {count}: {}",
+                    i + 1,
+                    fen::board_to_fen(&entry.board)
+                );
+            }
+        }
+    }
+
+    println!(
+        "\n{failed} failed, {nodes} nodes in {:?} ({} nps)",
+        start.elapsed(),
+        nps(nodes, start)
+    );
+    if failed > 0 {
+        std::process::exit(1);
+    }
+}
+
+// Read a divide from stdin, ending at a blank line or "Nodes searched" line
+// after the moves, or the end of the input. A total left over from the
+// previous divide is skipped.
+fn read_divide() -> Vec<(String, u64)> {
+    let mut text = String::new();
+    for line in std::io::stdin().lock().lines() {
+        let line =
+            line.unwrap_or_else(|e| fail(format!("Could not read the reference divide: {e}")));
+        let end = line.trim().is_empty() || line.starts_with("Nodes searched");
+        if end && !perft::parse_divide(&text).is_empty() {
+            break;
+        }
+        text.push_str(&line);
+        text.push('\n');
+    }
+    perft::parse_divide(&text)
+}
+
+// oxide perft diff <depth> [fen]
+//
+// Compares our divide with reference divides read from stdin. While a move has
+// the wrong count below it, the reference divide of the position after that
+// move is asked for, until the move generated wrongly is found.
+fn perft_diff(mut board: Board, mut depth: u32) {
+    let mut path = Vec::new();
+
+    loop {
+        eprintln!(
+            "Paste the reference divide of {} at depth {depth}:",
+            fen::board_to_fen(&board)
+        );
+        let reference = read_divide();
+        let ours = perft::perft_divide(&board, depth);
+        let diff = perft::diff_divide(&ours, &reference);
+
+        let moves = if path.is_empty() {
+            "the root".to_string()
+        } else {
+            path.join(" ")
+        };
+        match diff.first() {
+            None => {
+                println!("No differences after {moves}");
+                return;
+            }
+            Some(DivideMismatch::Count {
+                move_,
+                ours,
+                reference,
+            }) if depth > 1 => {
+                println!("{move_}: {ours}, reference {reference}");
+                path.push(move_.to_string());
+                board.make_move(move_);
+                depth -= 1;
+            }
+            Some(_) => {
+                println!("After {moves}:");
+                for mismatch in &diff {
+                    match mismatch {
+                        DivideMismatch::Missing(m) => println!("  missing move {m}"),
+                        DivideMismatch::Extra(m) => println!("  extra move {m}"),
+                        DivideMismatch::Count {
+                            move_,
+                            ours,
+                            reference,
+                        } => println!("  {move_}: {ours}, reference {reference}"),
+                    }
+                }
+                println!("Position: {}", fen::board_to_fen(&board));
+                return;
+            }
+        }
+    }
+}
+
+// Parse an optional argument, reporting the usage if it's invalid
+fn parse_arg<T: std::str::FromStr>(arg: Option<&String>, default: T, usage: &str) -> T {
+    match arg {
+        Some(s) => s
+            .parse()
+            .unwrap_or_else(|_| fail(format!("Invalid argument {s}\n\n{usage}"))),
+        None => default,
+    }
+}
+
+fn open(path: &str) -> File {
+    File::open(path).unwrap_or_else(|e| fail(format!("Could not open {path}: {e}")))
+}
+
+// The position given by the rest of the arguments, or the start position
+fn board_from_args(args: &[String]) -> Board {
+    let fen = if args.is_empty() {
+        START_FEN.to_string()
+    } else {
+        args.join(" ")
+    };
+    let board = fen::fen_to_board(&fen).unwrap_or_else(|e| fail(e));
+    if !board.has_one_king_each() {
+        fail(format!("Missing or extra king in {fen}"));
+    }
+    board
+}
+
+// oxide eval [fen]
+fn eval(args: &[String]) {
+    let board = board_from_args(args);
+    let trace = evaluation::eval_trace(&board).unwrap_or_else(|e| fail(e));
+    println!("{trace}");
+}
+
+// oxide mate <moves> [fen]
+fn mate(args: &[String]) {
+    let usage = "Usage: oxide mate <moves> [fen]";
+    let moves: u32 = args
+        .first()
+        .and_then(|n| n.parse().ok())
+        .filter(|&n| n > 0)
+        .unwrap_or_else(|| fail(usage));
+    let board = board_from_args(&args[1..]);
+
+    let start = Instant::now();
+    match mate_search::mate_search(&board, moves) {
+        MateResult::Mate { moves, pv } => {
+            let pv: Vec<String> = pv.iter().map(|m| m.to_string()).collect();
+            println!("Mate in {moves}: {}", pv.join(" "));
+        }
+        MateResult::NoMate => println!("No mate in {moves}"),
+    }
+    println!("Searched in {:?}", start.elapsed());
+}
+
+// oxide bench [EvalFile]
+//
+// Evaluates the bench positions and prints a signature of the evaluations, which
+// changes with the network in use. The EvalFile is a network path, or
+// <default> for the embedded network.
+fn bench(args: &[String]) {
+    let eval_file = args.first().map_or(nnue::DEFAULT_EVAL_FILE, String::as_str);
+    let start = Instant::now();
+
+    let (positions, signature) = match Network::from_eval_file(eval_file) {
+        Some(Ok(network)) => {
+            println!("Evaluation: network {eval_file}");
+            bench::run(|b| network.evaluate_board(b))
+        }
+        Some(Err(e)) => fail(e),
+        None => {
+            println!("Evaluation: hand crafted, no network is embedded");
+            let mut pawn_table = PawnHashTable::new(1024);
+            bench::run(|b| evaluation::evaluate(b, &mut pawn_table))
+        }
+    };
+
+    println!("{positions} positions in {:?}", start.elapsed());
+    println!("Bench: {signature:016x}");
+}
+
+// oxide tune <positions> [epochs] [learning rate]
+fn tune(args: &[String]) {
+    let usage = "Usage: oxide tune <positions> [epochs] [learning rate]";
+    let path = args.first().unwrap_or_else(|| fail(usage));
+    let epochs: usize = parse_arg(args.get(1), 1000, usage);
+    let learning_rate: f64 = parse_arg(args.get(2), 1.0, usage);
+
+    let entries = tuner::load_entries(BufReader::new(open(path))).unwrap_or_else(|e| fail(e));
+    eprintln!("Loaded {} positions", entries.len());
+
+    let mut tuner = Tuner::new(entries);
+    let k = tuner.fit_k();
+    eprintln!("K = {k:.4}, error = {:.6}", tuner.error(k));
+
+    for epoch in 1..=epochs {
+        tuner.step(learning_rate);
+        if epoch % 100 == 0 {
+            eprintln!("Epoch {epoch}, error = {:.6}", tuner.error(k));
+        }
+    }
+
+    tuner
+        .write_source(&mut std::io::stdout())
+        .unwrap_or_else(|e| fail(format!("Could not write the tuned parameters: {e}")));
+}
+
+// oxide train <packed data> <network> [epochs] [crelu|screlu]
+fn train(args: &[String]) {
+    let usage = "Usage: oxide train <packed data> <network> [epochs] [crelu|screlu]";
+    let (data, output) = match args {
+        [data, output, ..] => (data, output),
+        _ => fail(usage),
+    };
+    let epochs: usize = parse_arg(args.get(2), 10, usage);
+    let activation = match args.get(3).map(String::as_str) {
+        None | Some("screlu") => Activation::SCReLU,
+        Some("crelu") => Activation::CReLU,
+        Some(_) => fail(usage),
+    };
+
+    let mut samples =
+        nnue_trainer::load_samples(BufReader::new(open(data))).unwrap_or_else(|e| fail(e));
+    eprintln!("Loaded {} positions", samples.len());
+
+    let mut trainer = Trainer::new(TrainerConfig {
+        activation,
+        ..TrainerConfig::default()
+    });
+    for epoch in 1..=epochs {
+        let loss = trainer.train_epoch(&mut samples);
+        eprintln!("Epoch {epoch}, loss = {loss:.6}");
+
+        // save after every epoch, so that a long run can be stopped early
+        std::fs::write(output, trainer.network().to_bytes())
+            .unwrap_or_else(|e| fail(format!("Could not write {output}: {e}")));
+    }
+}
+
+// oxide pack <text> <packed>
+// oxide unpack <packed> <text>
+fn convert(args: &[String], pack: bool) {
+    let usage = if pack {
+        "Usage: oxide pack <text> <packed>"
+    } else {
+        "Usage: oxide unpack <packed> <text>"
+    };
+    let [input, output] = args else {
+        fail(usage);
+    };
+
+    let input = open(input);
+    let output =
+        File::create(output).unwrap_or_else(|e| fail(format!("Could not create {output}: {e}")));
+    let (input, output) = (BufReader::new(input), BufWriter::new(output));
+
+    let count = if pack {
+        training_data::text_to_packed(input, output)
+    } else {
+        training_data::packed_to_text(input, output)
+    }
+    .unwrap_or_else(|e| fail(e));
+    eprintln!("Converted {count} positions");
 }
@@ -0,0 +1,43 @@
+use crate::board::Board;
+use crate::fen;
+use crate::generate_moves::legal_moves;
+
+/// The positions the bench evaluates, along with every position one move on
+/// from each.
+pub const BENCH_FENS: [&str; 8] = [
+    "rnbqkbnr/pppppppp/8/8/8/8/PPPPPPPP/RNBQKBNR w KQkq - 0 1",
+    "r3k2r/p1ppqpb1/bn2pnp1/3PN3/1p2P3/2N2Q1p/PPPBBPPP/R3K2R w KQkq - 0 1",
+    "8/2p5/3p4/KP5r/1R3p1k/8/4P1P1/8 w - - 0 1",
+    "r3k2r/Pppp1ppp/1b3nbN/nP6/BBP1P3/q4N2/Pp1P2PP/R2Q1RK1 w kq - 0 1",
+    "rnbq1k1r/pp1Pbppp/2p5/8/2B5/8/PPP1NnPP/RNBQK2R w KQ - 1 8",
+    "r4rk1/1pp1qppp/p1np1n2/2b1p1B1/2B1P1b1/P1NP1N2/1PP1QPPP/R4RK1 w - - 0 10",
+    "3b4/5kp1/1p1p1p1p/pP1PpP1P/P1P1P3/3KN3/8/8 w - - 0 1",
+    "8/1p3pp1/7p/5P1P/2k3P1/8/2K2P2/8 w - - 0 1",
+];
+
+/// Evaluate every bench position, returning the number of positions and a
+/// signature of the evaluations. Any change to the evaluation, such as loading
+/// a different network, almost certainly changes the signature, so it shows
+/// which evaluation is in use.
+pub fn run<F: FnMut(&Board) -> i32>(mut evaluate: F) -> (usize, u64) {
+    let mut positions = 0;
+    let mut signature: u64 = 0xcbf2_9ce4_8422_2325;
+
+    for fen in BENCH_FENS {
+        let board = fen::fen_to_board(fen).unwrap();
+        let mut moves = Vec::new();
+        legal_moves(&board, &mut moves);
+
+        let children = moves.iter().map(|m| {
+            let mut child = board;
+            child.make_move(m);
+            child
+        });
+        for b in std::iter::once(board).chain(children) {
+            positions += 1;
+            signature = (signature ^ evaluate(&b) as u32 as u64).wrapping_mul(0x100_0000_01b3);
+        }
+    }
+
+    (positions, signature)
+}
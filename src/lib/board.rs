@@ -1,13 +1,56 @@
 use crate::{
     chess_move::{Move, MoveFlag},
     definitions::*,
+    generate_moves::{is_in_check, is_square_threatened},
+    zobrist::{self, PIECE_SQUARE_KEYS},
 };
 
+use arrayvec::ArrayVec;
 use strum::{EnumCount, IntoEnumIterator};
 
+/// The pieces a move removes from and adds to the board. A capture removes the
+/// captured piece, and castling moves the rook as well as the king.
+#[derive(Clone, Default)]
+pub struct MoveDelta {
+    pub removed: ArrayVec<(Piece, Square), 2>,
+    pub added: ArrayVec<(Piece, Square), 2>,
+}
+
+/// Why a move can't be made in the position.
+#[derive(Debug, PartialEq)]
+pub enum MoveError {
+    /// There is no piece of the side to move on the square.
+    NoPiece(Square),
+    /// The piece can't make the move, or the flag doesn't fit the position.
+    Invalid(Move),
+    /// The move leaves the king in check, or castles out of or through check.
+    Illegal(Move),
+}
+
+impl std::fmt::Display for MoveError {
+    fn fmt(&self, f: &mut std::fmt::Formatter) -> std::fmt::Result {
+        match self {
+            MoveError::NoPiece(square) => write!(f, "No piece to move on {square}"),
+            MoveError::Invalid(m) => write!(f, "Invalid move {m}"),
+            MoveError::Illegal(m) => write!(f, "Illegal move {m}, the king would be in check"),
+        }
+    }
+}
+
+impl std::error::Error for MoveError {}
+
 #[derive(Copy, Clone, PartialEq)]
 pub struct Board {
     pieces: [BB; Piece::COUNT],
+    // the same placement as the bitboards, for looking up the piece on a square
+    mailbox: [Option<Piece>; 64],
+    // occupancy of each side
+    sides: [BB; Side::COUNT],
+
+    // zobrist key of the piece placement, the rest of the key is added by key()
+    piece_key: u64,
+    // zobrist key of only the pawns, used to index the pawn hash table
+    pawn_key: u64,
 
     pub en_passant: Option<Square>,
     pub halfmove_clock: u32,
@@ -24,6 +67,10 @@ impl Board {
     pub fn new() -> Board {
         Board {
             pieces: [BB_EMPTY; Piece::COUNT],
+            mailbox: [None; 64],
+            sides: [BB_EMPTY; Side::COUNT],
+            piece_key: 0,
+            pawn_key: 0,
             en_passant: None,
             halfmove_clock: 0,
             fullmove_number: 1,
@@ -36,22 +83,38 @@ impl Board {
     }
 
     pub fn clear_square(&mut self, square: Square) {
-        for piece in self.pieces.iter_mut() {
-            *piece &= !square.to_bb();
+        if let Some(piece) = self.mailbox[square as usize].take() {
+            let key = PIECE_SQUARE_KEYS[piece as usize][square as usize];
+            self.piece_key ^= key;
+            if piece.get_type() == PieceType::Pawn {
+                self.pawn_key ^= key;
+            }
+
+            self.pieces[piece as usize] &= !square.to_bb();
+            self.sides[piece.get_side() as usize] &= !square.to_bb();
         }
     }
 
+    /// Put a piece on a square, replacing any piece already there.
     pub fn set_square(&mut self, square: Square, piece: Piece) {
+        if self.mailbox[square as usize] == Some(piece) {
+            return;
+        }
+        self.clear_square(square);
+
+        let key = PIECE_SQUARE_KEYS[piece as usize][square as usize];
+        self.piece_key ^= key;
+        if piece.get_type() == PieceType::Pawn {
+            self.pawn_key ^= key;
+        }
+
+        self.mailbox[square as usize] = Some(piece);
         self.pieces[piece as usize] |= square.to_bb();
+        self.sides[piece.get_side() as usize] |= square.to_bb();
     }
 
-    pub fn get_square(&self, square: Square) -> Option<Piece> {
-        for piece in Piece::iter() {
-            if self.pieces[piece as usize] & square.to_bb() != BB_EMPTY {
-                return Some(piece);
-            }
-        }
-        None
+    pub const fn get_square(&self, square: Square) -> Option<Piece> {
+        self.mailbox[square as usize]
     }
 
     pub fn empty_squares(&self) -> BB {
@@ -59,24 +122,84 @@ impl Board {
     }
 
     pub fn occupied_squares(&self) -> BB {
-        let mut bb = BB_EMPTY;
-        for piece in Piece::iter() {
-            bb |= self.pieces[piece as usize];
-        }
-        bb
+        self.sides[Side::White as usize] | self.sides[Side::Black as usize]
     }
 
     pub const fn get_piece_bb(&self, piece: Piece) -> BB {
         self.pieces[piece as usize]
     }
 
+    /// The zobrist key of the position. The move clocks aren't included.
+    pub fn key(&self) -> u64 {
+        self.piece_key ^ zobrist::state_key(self)
+    }
+
+    pub const fn pawn_key(&self) -> u64 {
+        self.pawn_key
+    }
+
+    /// Make a move, returning the pieces it added and removed so that anything
+    /// derived from the piece placement, like the NNUE accumulators, can be
+    /// updated incrementally.
+    pub fn make_move_with_delta(&mut self, m: &Move) -> MoveDelta {
+        let delta = self.move_delta(m);
+        self.make_move(m);
+        delta
+    }
+
+    // The pieces the move will add and remove, worked out before it is made
+    fn move_delta(&self, m: &Move) -> MoveDelta {
+        let from_piece = self
+            .get_square(m.from())
+            .unwrap_or_else(|| panic!("No piece at {:?}", m.from()));
+
+        let mut delta = MoveDelta::default();
+        delta.removed.push((from_piece, m.from()));
+        if m.is_capture() {
+            let captured_square = if m.flag() == MoveFlag::EnPassant {
+                Square::from_coord(m.to().file(), m.from().rank())
+            } else {
+                m.to()
+            };
+            if let Some(captured) = self.get_square(captured_square) {
+                delta.removed.push((captured, captured_square));
+            }
+        }
+
+        let promoted = match m.flag() {
+            MoveFlag::KnightPromotion | MoveFlag::KnightPromotionCapture => Some(PieceType::Knight),
+            MoveFlag::BishopPromotion | MoveFlag::BishopPromotionCapture => Some(PieceType::Bishop),
+            MoveFlag::RookPromotion | MoveFlag::RookPromotionCapture => Some(PieceType::Rook),
+            MoveFlag::QueenPromotion | MoveFlag::QueenPromotionCapture => Some(PieceType::Queen),
+            _ => None,
+        };
+        let to_piece = promoted.map_or(from_piece, |t| Piece::from_type(t, self.stm));
+        delta.added.push((to_piece, m.to()));
+
+        // the rook's move when castling
+        let rook = Piece::from_type(PieceType::Rook, self.stm);
+        let rook_move = match (m.flag(), self.stm) {
+            (MoveFlag::KingCastle, Side::White) => Some((Square::H1, Square::F1)),
+            (MoveFlag::QueenCastle, Side::White) => Some((Square::A1, Square::D1)),
+            (MoveFlag::KingCastle, Side::Black) => Some((Square::H8, Square::F8)),
+            (MoveFlag::QueenCastle, Side::Black) => Some((Square::A8, Square::D8)),
+            _ => None,
+        };
+        if let Some((from, to)) = rook_move {
+            delta.removed.push((rook, from));
+            delta.added.push((rook, to));
+        }
+
+        delta
+    }
+
     pub fn make_move(&mut self, m: &Move) {
         let from_piece = self
-            .get_square(m.from)
-            .unwrap_or_else(|| panic!("No piece at {:?}", m.from));
+            .get_square(m.from())
+            .unwrap_or_else(|| panic!("No piece at {:?}", m.from()));
 
         if m.is_capture() {
-            self.clear_square(m.to);
+            self.clear_square(m.to());
         }
 
         if m.is_capture() || from_piece == Piece::from_type(PieceType::Pawn, self.stm) {
@@ -87,59 +210,61 @@ impl Board {
 
         // set the 'to' square with the 'from' piece, except if it's a promotion
         if !m.is_promotion() {
-            self.set_square(m.to, from_piece);
+            self.set_square(m.to(), from_piece);
         }
 
         // for castle moves, make sure we move the rook
-        if m.flag == MoveFlag::KingCastle && self.stm == Side::White {
+        if m.flag() == MoveFlag::KingCastle && self.stm == Side::White {
             self.set_square(Square::F1, Piece::WhiteRook);
             self.clear_square(Square::H1);
         }
 
-        if m.flag == MoveFlag::QueenCastle && self.stm == Side::White {
+        if m.flag() == MoveFlag::QueenCastle && self.stm == Side::White {
             self.set_square(Square::D1, Piece::WhiteRook);
             self.clear_square(Square::A1);
         }
 
-        if m.flag == MoveFlag::KingCastle && self.stm == Side::Black {
+        if m.flag() == MoveFlag::KingCastle && self.stm == Side::Black {
             self.set_square(Square::F8, Piece::BlackRook);
             self.clear_square(Square::H8);
         }
 
-        if m.flag == MoveFlag::QueenCastle && self.stm == Side::Black {
+        if m.flag() == MoveFlag::QueenCastle && self.stm == Side::Black {
             self.set_square(Square::D8, Piece::BlackRook);
             self.clear_square(Square::A8);
         }
 
-        if m.flag == MoveFlag::PawnDoubleMove {
+        if m.flag() == MoveFlag::PawnDoubleMove {
             // For the context of the 3-fold repetition rule, an ep square only should be set
             // if there is a pawn that could legally do the ep capture. This is somewhat of a
             // strange quirk in the chess rules and is a pain to implement so we ignore this.
-            self.en_passant = Some(Square::from_index((m.to as usize + m.from as usize) / 2));
+            self.en_passant = Some(Square::from_index(
+                (m.to() as usize + m.from() as usize) / 2,
+            ));
         } else {
             self.en_passant = None;
         }
 
-        if m.flag == MoveFlag::EnPassant {
+        if m.flag() == MoveFlag::EnPassant {
             // This works for white or black. The ep capture happens on the file of the target
             // square, but on the rank of the from square.
-            self.clear_square(Square::from_coord(m.to.file(), m.from.rank()));
+            self.clear_square(Square::from_coord(m.to().file(), m.from().rank()));
         }
 
-        if m.flag == MoveFlag::KnightPromotion || m.flag == MoveFlag::KnightPromotionCapture {
-            self.set_square(m.to, Piece::from_type(PieceType::Knight, self.stm));
+        if m.flag() == MoveFlag::KnightPromotion || m.flag() == MoveFlag::KnightPromotionCapture {
+            self.set_square(m.to(), Piece::from_type(PieceType::Knight, self.stm));
         }
 
-        if m.flag == MoveFlag::BishopPromotion || m.flag == MoveFlag::BishopPromotionCapture {
-            self.set_square(m.to, Piece::from_type(PieceType::Bishop, self.stm));
+        if m.flag() == MoveFlag::BishopPromotion || m.flag() == MoveFlag::BishopPromotionCapture {
+            self.set_square(m.to(), Piece::from_type(PieceType::Bishop, self.stm));
         }
 
-        if m.flag == MoveFlag::RookPromotion || m.flag == MoveFlag::RookPromotionCapture {
-            self.set_square(m.to, Piece::from_type(PieceType::Rook, self.stm));
+        if m.flag() == MoveFlag::RookPromotion || m.flag() == MoveFlag::RookPromotionCapture {
+            self.set_square(m.to(), Piece::from_type(PieceType::Rook, self.stm));
         }
 
-        if m.flag == MoveFlag::QueenPromotion || m.flag == MoveFlag::QueenPromotionCapture {
-            self.set_square(m.to, Piece::from_type(PieceType::Queen, self.stm));
+        if m.flag() == MoveFlag::QueenPromotion || m.flag() == MoveFlag::QueenPromotionCapture {
+            self.set_square(m.to(), Piece::from_type(PieceType::Queen, self.stm));
         }
 
         if self.stm == Side::White {
@@ -147,49 +272,323 @@ impl Board {
         }
 
         self.stm = !self.stm;
-        self.clear_square(m.from);
+        self.clear_square(m.from());
         self.update_castle_rights(m);
+
+        debug_assert!(self.is_consistent(), "board out of sync after {}", m);
     }
 
+    /// Make a move that may not be legal, such as one from the hash table or
+    /// the user. The board is unchanged if the move is rejected.
+    pub fn try_make_move(&mut self, m: &Move) -> Result<(), MoveError> {
+        match self.get_square(m.from()) {
+            Some(piece) if piece.get_side() == self.stm => {}
+            _ => return Err(MoveError::NoPiece(m.from())),
+        }
+        if !self.is_pseudo_legal(*m) {
+            return Err(MoveError::Invalid(*m));
+        }
+        if !self.is_king_safe(*m) {
+            return Err(MoveError::Illegal(*m));
+        }
+        self.make_move(m);
+        Ok(())
+    }
+
+    /// Whether the move is legal in the position.
+    pub fn is_legal(&self, m: Move) -> bool {
+        self.is_pseudo_legal(m) && self.is_king_safe(m)
+    }
+
+    /// Whether the side to move has a piece that can make the move, ignoring
+    /// whether it leaves the king in check. Castling is checked for the rights
+    /// and an empty path but not for attacked squares. This is much cheaper
+    /// than generating every move to look for it.
+    pub fn is_pseudo_legal(&self, m: Move) -> bool {
+        let piece = match self.get_square(m.from()) {
+            Some(piece) if piece.get_side() == self.stm => piece,
+            _ => return false,
+        };
+        let to_bb = m.to().to_bb();
+        let occupied = self.occupied_squares();
+
+        match m.flag() {
+            MoveFlag::KingCastle | MoveFlag::QueenCastle => return self.can_castle(m),
+            MoveFlag::EnPassant => {
+                let captured = Square::from_coord(m.to().file(), m.from().rank());
+                return piece.get_type() == PieceType::Pawn
+                    && self.en_passant == Some(m.to())
+                    && PAWN_ATTACKS[self.stm as usize][m.from() as usize] & to_bb != BB_EMPTY
+                    && self.get_square(captured)
+                        == Some(Piece::from_type(PieceType::Pawn, !self.stm));
+            }
+            _ => {}
+        }
+
+        // captures need an enemy piece on the target, everything else an empty square
+        let target = self.get_square(m.to());
+        let target_ok = if m.is_capture() {
+            target.is_some_and(|t| t.get_side() != self.stm)
+        } else {
+            target.is_none()
+        };
+        if !target_ok {
+            return false;
+        }
+
+        if piece.get_type() != PieceType::Pawn {
+            let attacks = match piece.get_type() {
+                PieceType::Knight => KNIGHT_ATTACKS[m.from() as usize],
+                PieceType::Bishop => bishop_attacks(m.from(), occupied),
+                PieceType::Rook => rook_attacks(m.from(), occupied),
+                PieceType::Queen => queen_attacks(m.from(), occupied),
+                _ => KING_ATTACKS[m.from() as usize],
+            };
+            return matches!(m.flag(), MoveFlag::Quiet | MoveFlag::Capture)
+                && attacks & to_bb != BB_EMPTY;
+        }
+
+        let (push, start_rank, last_rank) = match self.stm {
+            Side::White => (m.from().to_bb() << 8, Rank::Two, Rank::Eight),
+            Side::Black => (m.from().to_bb() >> 8, Rank::Seven, Rank::One),
+        };
+        // pawns reaching the last rank must promote, and only there
+        if m.is_promotion() != (m.to().rank() == last_rank) {
+            return false;
+        }
+
+        match m.flag() {
+            MoveFlag::PawnDoubleMove => {
+                let double_push = match self.stm {
+                    Side::White => push << 8,
+                    Side::Black => push >> 8,
+                };
+                m.from().rank() == start_rank && push & occupied == BB_EMPTY && double_push == to_bb
+            }
+            _ if m.is_capture() => {
+                PAWN_ATTACKS[self.stm as usize][m.from() as usize] & to_bb != BB_EMPTY
+            }
+            _ => push == to_bb,
+        }
+    }
+
+    // Whether the castle move has the rights, the king and rook in place and
+    // nothing between them
+    fn can_castle(&self, m: Move) -> bool {
+        let (king, to, rook, right) = match (self.stm, m.flag()) {
+            (Side::White, MoveFlag::KingCastle) => {
+                (Square::E1, Square::G1, Square::H1, self.white_king_castle)
+            }
+            (Side::White, _) => (Square::E1, Square::C1, Square::A1, self.white_queen_castle),
+            (Side::Black, MoveFlag::KingCastle) => {
+                (Square::E8, Square::G8, Square::H8, self.black_king_castle)
+            }
+            (Side::Black, _) => (Square::E8, Square::C8, Square::A8, self.black_queen_castle),
+        };
+
+        right
+            && m.from() == king
+            && m.to() == to
+            && self.get_square(king) == Some(Piece::from_type(PieceType::King, self.stm))
+            && self.get_square(rook) == Some(Piece::from_type(PieceType::Rook, self.stm))
+            && in_between(king, rook) & self.occupied_squares() == BB_EMPTY
+    }
+
+    // Whether a pseudo legal move keeps the king out of check
+    fn is_king_safe(&self, m: Move) -> bool {
+        if m.is_castle() {
+            // the king can't castle out of or through check
+            let passed = Square::from_index((m.from() as usize + m.to() as usize) / 2);
+            if is_square_threatened(self, m.from(), self.stm)
+                || is_square_threatened(self, passed, self.stm)
+            {
+                return false;
+            }
+        }
+
+        let mut board = *self;
+        board.make_move(&m);
+        !is_in_check(&board, self.stm)
+    }
+
+    /// Panics if `s` has no king, see `has_one_king_each`.
     pub const fn get_king(&self, s: Side) -> Square {
         self.get_piece_bb(Piece::from_type(PieceType::King, s))
             .ctz()
     }
 
-    pub fn get_pieces(&self, s: Side) -> BB {
-        self.get_piece_bb(Piece::from_type(PieceType::Pawn, s))
-            | self.get_piece_bb(Piece::from_type(PieceType::Knight, s))
-            | self.get_piece_bb(Piece::from_type(PieceType::Bishop, s))
-            | self.get_piece_bb(Piece::from_type(PieceType::Rook, s))
-            | self.get_piece_bb(Piece::from_type(PieceType::Queen, s))
-            | self.get_piece_bb(Piece::from_type(PieceType::King, s))
+    /// Whether each side has exactly one king. The FEN parser accepts any
+    /// number of kings, but move generation and evaluation assume one each.
+    pub const fn has_one_king_each(&self) -> bool {
+        self.get_piece_bb(Piece::WhiteKing).popcnt() == 1
+            && self.get_piece_bb(Piece::BlackKing).popcnt() == 1
+    }
+
+    pub const fn get_pieces(&self, s: Side) -> BB {
+        self.sides[s as usize]
+    }
+
+    /// Whether the mailbox and the side occupancy agree with the piece
+    /// bitboards. This is checked after every move in debug builds.
+    pub fn is_consistent(&self) -> bool {
+        let mut sides = [BB_EMPTY; Side::COUNT];
+        let mut count = 0;
+        for piece in Piece::iter() {
+            let mut bb = self.pieces[piece as usize];
+            sides[piece.get_side() as usize] |= bb;
+            count += bb.popcnt();
+            while bb != BB_EMPTY {
+                if self.mailbox[bb.poplsb() as usize] != Some(piece) {
+                    return false;
+                }
+            }
+        }
+
+        // every piece is on its own square, and the mailbox has nothing else
+        sides == self.sides
+            && count == self.occupied_squares().popcnt()
+            && count == self.mailbox.iter().flatten().count() as u32
     }
 
     fn update_castle_rights(&mut self, m: &Move) {
-        if m.from == Square::E1 {
+        if m.from() == Square::E1 {
             self.white_king_castle = false;
             self.white_queen_castle = false;
         }
 
-        if m.from == Square::E8 {
+        if m.from() == Square::E8 {
             self.black_king_castle = false;
             self.black_queen_castle = false;
         }
 
-        if m.from == Square::A1 || m.to == Square::A1 {
+        if m.from() == Square::A1 || m.to() == Square::A1 {
             self.white_queen_castle = false;
         }
 
-        if m.from == Square::A8 || m.to == Square::A8 {
+        if m.from() == Square::A8 || m.to() == Square::A8 {
             self.black_queen_castle = false;
         }
 
-        if m.from == Square::H1 || m.to == Square::H1 {
+        if m.from() == Square::H1 || m.to() == Square::H1 {
             self.white_king_castle = false;
         }
 
-        if m.from == Square::H8 || m.to == Square::H8 {
+        if m.from() == Square::H8 || m.to() == Square::H8 {
             self.black_king_castle = false;
         }
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use crate::{board::*, fen, generate_moves, zobrist};
+
+    #[test]
+    fn set_square_replaces() {
+        let mut board = fen::fen_to_board("4k3/8/8/8/3n4/8/4P3/4K3 w - - 0 1").unwrap();
+
+        // a piece over a piece of the other side, and over a pawn
+        board.set_square(Square::D4, Piece::WhiteQueen);
+        board.set_square(Square::E2, Piece::WhiteRook);
+        assert!(board.get_square(Square::D4) == Some(Piece::WhiteQueen));
+        assert!(board.get_square(Square::E2) == Some(Piece::WhiteRook));
+        assert!(board.get_piece_bb(Piece::BlackKnight) == BB_EMPTY);
+        assert!(board.get_piece_bb(Piece::WhitePawn) == BB_EMPTY);
+        assert!(board.get_piece_bb(Piece::WhiteQueen) == Square::D4.to_bb());
+        assert!(board.get_pieces(Side::Black) == Square::E8.to_bb());
+        assert!(
+            board.get_pieces(Side::White)
+                == Square::D4.to_bb() | Square::E2.to_bb() | Square::E1.to_bb()
+        );
+        assert!(board.is_consistent());
+        assert_eq!(board.key(), zobrist::key(&board));
+
+        // setting the same piece again changes nothing
+        let before = board;
+        board.set_square(Square::D4, Piece::WhiteQueen);
+        assert!(board == before);
+
+        // the keys match the same position set up from scratch
+        let expected = fen::fen_to_board("4k3/8/8/8/3Q4/8/4R3/4K3 w - - 0 1").unwrap();
+        assert!(board == expected);
+        assert_eq!(board.key(), expected.key());
+        assert_eq!(board.pawn_key(), expected.pawn_key());
+    }
+
+    // Check every possible move against the move generator, in the position and
+    // each position after one move
+    fn check_legality(fen: &str) {
+        let board = fen::fen_to_board(fen).unwrap();
+        let mut moves = Vec::new();
+        generate_moves::legal_moves(&board, &mut moves);
+
+        for b in std::iter::once(board).chain(moves.iter().map(|m| {
+            let mut child = board;
+            child.make_move(m);
+            child
+        })) {
+            let mut legal = Vec::new();
+            generate_moves::legal_moves(&b, &mut legal);
+            for m in (0..=u16::MAX).filter_map(Move::from_u16) {
+                assert_eq!(b.is_legal(m), legal.contains(&m), "{m:?} in {fen}");
+            }
+            assert!(legal.iter().all(|&m| b.is_pseudo_legal(m)));
+        }
+    }
+
+    #[test]
+    fn legality_matches_generator() {
+        for fen in [
+            "rnbqkbnr/pppppppp/8/8/8/8/PPPPPPPP/RNBQKBNR w KQkq - 0 1",
+            "r3k2r/p1ppqpb1/bn2pnp1/3PN3/1p2P3/2N2Q1p/PPPBBPPP/R3K2R w KQkq - 0 1",
+            "8/2p5/3p4/KP5r/1R3p1k/8/4P1P1/8 w - - 0 1",
+            "r3k2r/Pppp1ppp/1b3nbN/nP6/BBP1P3/q4N2/Pp1P2PP/R2Q1RK1 w kq - 0 1",
+            "rnbq1k1r/pp1Pbppp/2p5/8/2B5/8/PPP1NnPP/RNBQK2R w KQ - 1 8",
+            "8/8/8/2k5/3Pp3/8/8/4K2R b K d3 0 1",
+        ] {
+            check_legality(fen);
+        }
+    }
+
+    #[test]
+    fn try_make_move() {
+        // white is in check from the rook on g1 and can only castle queen side
+        let start = fen::fen_to_board("r3k2r/8/8/8/8/8/8/R3K1r1 w Qkq - 0 1").unwrap();
+        let mut board = start;
+
+        let errors = [
+            (
+                Move::new(Square::E4, Square::E5, MoveFlag::Quiet),
+                MoveError::NoPiece(Square::E4),
+            ),
+            (
+                Move::new(Square::A8, Square::A7, MoveFlag::Quiet),
+                MoveError::NoPiece(Square::A8),
+            ),
+            (
+                Move::new(Square::A1, Square::A8, MoveFlag::Quiet),
+                MoveError::Invalid(Move::new(Square::A1, Square::A8, MoveFlag::Quiet)),
+            ),
+            (
+                Move::new(Square::E1, Square::G1, MoveFlag::KingCastle),
+                MoveError::Invalid(Move::new(Square::E1, Square::G1, MoveFlag::KingCastle)),
+            ),
+            (
+                Move::new(Square::E1, Square::C1, MoveFlag::QueenCastle),
+                MoveError::Illegal(Move::new(Square::E1, Square::C1, MoveFlag::QueenCastle)),
+            ),
+            (
+                Move::new(Square::A1, Square::A2, MoveFlag::Quiet),
+                MoveError::Illegal(Move::new(Square::A1, Square::A2, MoveFlag::Quiet)),
+            ),
+        ];
+        for (m, error) in errors {
+            assert_eq!(board.try_make_move(&m).err(), Some(error));
+            assert!(board == start);
+        }
+
+        let m = Move::new(Square::E1, Square::F2, MoveFlag::Quiet);
+        assert_eq!(board.try_make_move(&m).err(), None);
+        assert!(board != start);
+    }
+}
@@ -18,32 +18,146 @@ pub enum MoveFlag {
     QueenPromotionCapture,  //1111
 }
 
-#[derive(Debug, Copy, Clone)]
-pub struct Move {
-    pub from: Square,
-    pub to: Square,
-    pub flag: MoveFlag,
+impl MoveFlag {
+    /// The flag with the given 4 bit code, `None` for the two unused codes.
+    pub const fn from_bits(bits: u8) -> Option<MoveFlag> {
+        match bits {
+            0 => Some(MoveFlag::Quiet),
+            1 => Some(MoveFlag::PawnDoubleMove),
+            2 => Some(MoveFlag::KingCastle),
+            3 => Some(MoveFlag::QueenCastle),
+            4 => Some(MoveFlag::Capture),
+            5 => Some(MoveFlag::EnPassant),
+            8 => Some(MoveFlag::KnightPromotion),
+            9 => Some(MoveFlag::BishopPromotion),
+            10 => Some(MoveFlag::RookPromotion),
+            11 => Some(MoveFlag::QueenPromotion),
+            12 => Some(MoveFlag::KnightPromotionCapture),
+            13 => Some(MoveFlag::BishopPromotionCapture),
+            14 => Some(MoveFlag::RookPromotionCapture),
+            15 => Some(MoveFlag::QueenPromotionCapture),
+            _ => None,
+        }
+    }
 }
 
+/// A move packed into 16 bits: the from square in bits 0-5, the to square in
+/// bits 6-11 and the flag in bits 12-15.
+#[derive(Copy, Clone, PartialEq, Eq, Hash)]
+pub struct Move(u16);
+
 impl Move {
+    /// A quiet move from a1 to a1, which is never legal, for empty entries in
+    /// tables.
+    pub const NULL: Move = Move(0);
+
+    pub const fn new(from: Square, to: Square, flag: MoveFlag) -> Move {
+        Move(from as u16 | (to as u16) << 6 | (flag as u16) << 12)
+    }
+
+    pub const fn from(&self) -> Square {
+        Square::from_index((self.0 & 0x3f) as usize)
+    }
+
+    pub const fn to(&self) -> Square {
+        Square::from_index((self.0 >> 6 & 0x3f) as usize)
+    }
+
+    pub const fn flag(&self) -> MoveFlag {
+        match MoveFlag::from_bits((self.0 >> 12) as u8) {
+            Some(flag) => flag,
+            None => unreachable!(),
+        }
+    }
+
+    pub const fn to_u16(self) -> u16 {
+        self.0
+    }
+
+    /// The move packed in `bits`, `None` if the flag is one of the unused
+    /// codes.
+    pub const fn from_u16(bits: u16) -> Option<Move> {
+        match MoveFlag::from_bits((bits >> 12) as u8) {
+            Some(_) => Some(Move(bits)),
+            None => None,
+        }
+    }
+
     pub const fn is_promotion(&self) -> bool {
-        return self.flag as u8 & 0b1000 != 0;
+        self.0 & 0b1000 << 12 != 0
     }
 
     pub const fn is_capture(&self) -> bool {
-        return self.flag as u8 & 0b0100 != 0;
+        self.0 & 0b0100 << 12 != 0
     }
 
     pub const fn is_castle(&self) -> bool {
-        match self.flag {
-            MoveFlag::KingCastle | MoveFlag::QueenCastle => true,
-            _ => false,
-        }
+        matches!(self.flag(), MoveFlag::KingCastle | MoveFlag::QueenCastle)
+    }
+}
+
+impl From<Move> for u16 {
+    fn from(m: Move) -> u16 {
+        m.to_u16()
+    }
+}
+
+impl std::fmt::Debug for Move {
+    fn fmt(&self, f: &mut std::fmt::Formatter) -> std::fmt::Result {
+        write!(f, "Move({self}, {:?})", self.flag())
     }
 }
 
 impl std::fmt::Display for Move {
     fn fmt(&self, f: &mut std::fmt::Formatter) -> std::fmt::Result {
-        write!(f, "{}{}", self.from, self.to)
+        write!(f, "{}{}", self.from(), self.to())?;
+        match self.flag() {
+            MoveFlag::KnightPromotion | MoveFlag::KnightPromotionCapture => write!(f, "n"),
+            MoveFlag::BishopPromotion | MoveFlag::BishopPromotionCapture => write!(f, "b"),
+            MoveFlag::RookPromotion | MoveFlag::RookPromotionCapture => write!(f, "r"),
+            MoveFlag::QueenPromotion | MoveFlag::QueenPromotionCapture => write!(f, "q"),
+            _ => Ok(()),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::chess_move::*;
+
+    use strum::EnumCount;
+
+    #[test]
+    fn packing_round_trip() {
+        for bits in 0..=u16::MAX {
+            match Move::from_u16(bits) {
+                Some(m) => {
+                    assert_eq!(m.to_u16(), bits);
+                    let unpacked = Move::new(m.from(), m.to(), m.flag());
+                    assert_eq!(unpacked, m);
+                }
+                None => assert!(matches!(bits >> 12, 6 | 7)),
+            }
+        }
+        assert_eq!(std::mem::size_of::<Move>(), 2);
+    }
+
+    #[test]
+    fn fields() {
+        for from in 0..Square::COUNT {
+            for to in [0, 17, 63] {
+                let (from, to) = (Square::from_index(from), Square::from_index(to));
+                let m = Move::new(from, to, MoveFlag::QueenPromotionCapture);
+                assert_eq!((m.from(), m.to()), (from, to));
+                assert_eq!(m.flag(), MoveFlag::QueenPromotionCapture);
+                assert!(m.is_capture() && m.is_promotion() && !m.is_castle());
+            }
+        }
+
+        let null = Move::NULL;
+        assert_eq!((null.from(), null.to()), (Square::A1, Square::A1));
+        assert_eq!(null.flag(), MoveFlag::Quiet);
+        assert_eq!(null.to_string(), "a1a1");
+        assert!(Move::new(Square::E1, Square::G1, MoveFlag::KingCastle) != Move::NULL);
     }
 }
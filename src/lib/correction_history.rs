@@ -0,0 +1,160 @@
+use crate::board::Board;
+use crate::definitions::*;
+use crate::endgame;
+
+use strum::EnumCount;
+
+/// The number of entries in each table, for each side to move.
+pub const CORRECTION_SIZE: usize = 16384;
+
+/// Corrections are stored in 1/GRAIN of a centipawn, so that small updates
+/// aren't lost to rounding.
+const GRAIN: i32 = 256;
+/// The largest correction, in centipawns.
+const MAX_CORRECTION: i32 = 128;
+/// Updates are a weighted average, the weight of the new value is at most
+/// `MAX_WEIGHT / WEIGHT_SCALE`.
+const WEIGHT_SCALE: i32 = 256;
+const MAX_WEIGHT: i32 = 16;
+
+// The material key with the pawn counts masked out
+const PAWN_COUNTS: u64 =
+    0xf << (Piece::WhitePawn as usize * 4) | 0xf << (Piece::BlackPawn as usize * 4);
+
+/// The material signature of the pieces other than pawns.
+pub fn non_pawn_key(board: &Board) -> u64 {
+    endgame::material_key(board) & !PAWN_COUNTS
+}
+
+// Spread the keys over the table, the material keys only use a few low bits
+const fn index(key: u64) -> usize {
+    (key.wrapping_mul(0x9e37_79b9_7f4a_7c15) >> (64 - CORRECTION_SIZE.trailing_zeros())) as usize
+}
+
+/// Tracks how far the search result differs from the static evaluation,
+/// keyed by the pawn structure and by the non-pawn material, so that the
+/// static evaluation can be corrected in similar positions. Corrections are
+/// from the side to move's point of view.
+pub struct CorrectionHistory {
+    pawn: Vec<[i32; CORRECTION_SIZE]>,
+    non_pawn: Vec<[i32; CORRECTION_SIZE]>,
+}
+
+impl CorrectionHistory {
+    pub fn new() -> CorrectionHistory {
+        CorrectionHistory {
+            pawn: vec![[0; CORRECTION_SIZE]; Side::COUNT],
+            non_pawn: vec![[0; CORRECTION_SIZE]; Side::COUNT],
+        }
+    }
+
+    pub fn clear(&mut self) {
+        for table in self.pawn.iter_mut().chain(self.non_pawn.iter_mut()) {
+            table.fill(0);
+        }
+    }
+
+    // The entries for the position
+    fn entries(&mut self, board: &Board) -> [&mut i32; 2] {
+        let stm = board.stm as usize;
+        [
+            &mut self.pawn[stm][index(board.pawn_key())],
+            &mut self.non_pawn[stm][index(non_pawn_key(board))],
+        ]
+    }
+
+    /// The correction for the position, in centipawns.
+    pub fn correction(&self, board: &Board) -> i32 {
+        let stm = board.stm as usize;
+        let pawn = self.pawn[stm][index(board.pawn_key())];
+        let non_pawn = self.non_pawn[stm][index(non_pawn_key(board))];
+        (pawn + non_pawn) / 2 / GRAIN
+    }
+
+    /// Apply the correction to a static evaluation from the side to move's
+    /// point of view. This is done before the evaluation is used for pruning.
+    pub fn correct(&self, board: &Board, static_eval: i32) -> i32 {
+        static_eval + self.correction(board)
+    }
+
+    /// Record the result of a search of the position to `depth`. Deeper
+    /// searches move the correction further. The caller should skip positions
+    /// in check, mate scores, captures as the best move and bounds on the wrong
+    /// side of the static evaluation, where the difference says little about
+    /// the evaluation.
+    pub fn update(&mut self, board: &Board, depth: i32, static_eval: i32, score: i32) {
+        let diff = (score - static_eval).clamp(-MAX_CORRECTION, MAX_CORRECTION) * GRAIN;
+        let weight = (depth + 1).clamp(1, MAX_WEIGHT);
+
+        for entry in self.entries(board) {
+            *entry = (*entry * (WEIGHT_SCALE - weight) + diff * weight) / WEIGHT_SCALE;
+        }
+    }
+}
+
+impl Default for CorrectionHistory {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::{correction_history::*, fen};
+
+    #[test]
+    fn corrections_converge() {
+        let board =
+            fen::fen_to_board("r1bqkbnr/pppp1ppp/2n5/4p3/4P3/5N2/PPPP1PPP/RNBQKB1R w KQkq - 2 3")
+                .unwrap();
+        let mut history = CorrectionHistory::new();
+        assert_eq!(history.correct(&board, 35), 35);
+
+        for _ in 0..200 {
+            history.update(&board, 10, 35, 75);
+        }
+        assert!((38..=40).contains(&history.correction(&board)));
+
+        // deeper searches count for more
+        let mut shallow = CorrectionHistory::new();
+        let mut deep = CorrectionHistory::new();
+        shallow.update(&board, 1, 0, 100);
+        deep.update(&board, 12, 0, 100);
+        assert!(shallow.correction(&board) < deep.correction(&board));
+
+        // the correction is bounded however far the search is from the eval
+        for _ in 0..1000 {
+            history.update(&board, 20, 0, 5000);
+        }
+        assert!(history.correction(&board) <= MAX_CORRECTION);
+
+        history.clear();
+        assert_eq!(history.correction(&board), 0);
+    }
+
+    #[test]
+    fn keys() {
+        let mut history = CorrectionHistory::new();
+        let board = fen::fen_to_board("4k3/pp6/8/8/8/8/PPP5/R3K3 w - - 0 1").unwrap();
+        for _ in 0..100 {
+            history.update(&board, 10, 0, 100);
+        }
+
+        // same pawns and material with different pieces placement
+        let moved = fen::fen_to_board("3k4/pp6/8/8/8/8/PPP5/4K2R w - - 0 1").unwrap();
+        assert_eq!(history.correction(&moved), history.correction(&board));
+
+        // only the pawns differ, the non-pawn table still applies
+        let pawns = fen::fen_to_board("4k3/p1p5/8/8/8/8/PPP5/R3K3 w - - 0 1").unwrap();
+        assert!(history.correction(&pawns) > 0);
+        assert!(history.correction(&pawns) < history.correction(&board));
+
+        // corrections are kept for each side to move
+        let black = fen::fen_to_board("4k3/pp6/8/8/8/8/PPP5/R3K3 b - - 0 1").unwrap();
+        assert_eq!(history.correction(&black), 0);
+
+        assert_eq!(non_pawn_key(&board), non_pawn_key(&pawns));
+        let no_rook = fen::fen_to_board("4k3/pp6/8/8/8/8/PPP5/4K3 w - - 0 1").unwrap();
+        assert!(non_pawn_key(&board) != non_pawn_key(&no_rook));
+    }
+}
@@ -50,8 +50,27 @@ impl Piece {
             (PieceType::King, Side::Black) => Piece::BlackKing,
         }
     }
+
+    pub const fn get_type(&self) -> PieceType {
+        match self {
+            Piece::WhitePawn | Piece::BlackPawn => PieceType::Pawn,
+            Piece::WhiteKnight | Piece::BlackKnight => PieceType::Knight,
+            Piece::WhiteBishop | Piece::BlackBishop => PieceType::Bishop,
+            Piece::WhiteRook | Piece::BlackRook => PieceType::Rook,
+            Piece::WhiteQueen | Piece::BlackQueen => PieceType::Queen,
+            Piece::WhiteKing | Piece::BlackKing => PieceType::King,
+        }
+    }
+
+    pub const fn get_side(&self) -> Side {
+        match *self as usize / PieceType::COUNT {
+            0 => Side::White,
+            _ => Side::Black,
+        }
+    }
 }
 
+#[derive(EnumIter, EnumCount, Copy, Clone, PartialEq, Debug)]
 pub enum PieceType {
     Pawn,
     Knight,
@@ -197,6 +216,21 @@ impl Square {
         AntiDiagonal::from_index(14 - (*self as usize % 8) - (*self as usize / 8))
     }
 
+    /// Returns the rank of the square from the point of view of the given side.
+    ///
+    /// # Examples
+    /// ```
+    /// # use oxide::definitions::*;
+    /// assert_eq!(Square::G2.relative_rank(Side::White), Rank::Two);
+    /// assert_eq!(Square::G2.relative_rank(Side::Black), Rank::Seven);
+    /// ```
+    pub const fn relative_rank(&self, s: Side) -> Rank {
+        match s {
+            Side::White => self.rank(),
+            Side::Black => Rank::from_index(7 - *self as usize / 8),
+        }
+    }
+
     pub const fn from_coord(file: File, rank: Rank) -> Square {
         Square::from_index(rank as usize * 8 + file as usize)
     }
@@ -204,6 +238,23 @@ impl Square {
     pub const fn to_bb(&self) -> BB {
         BB(1 << *self as i32)
     }
+
+    /// Returns the number of king moves between the two squares.
+    ///
+    /// # Examples
+    /// ```
+    /// # use oxide::definitions::*;
+    /// assert_eq!(Square::B2.distance(Square::E4), 3);
+    /// ```
+    pub const fn distance(&self, other: Square) -> u32 {
+        let file_diff = (*self as i32 % 8 - other as i32 % 8).unsigned_abs();
+        let rank_diff = (*self as i32 / 8 - other as i32 / 8).unsigned_abs();
+        if file_diff > rank_diff {
+            file_diff
+        } else {
+            rank_diff
+        }
+    }
 }
 
 impl std::fmt::Display for Square {
@@ -478,6 +529,18 @@ impl BB {
         self.0 &= self.0 - 1;
         index
     }
+
+    /// return the number of set bits in the bitboard.
+    ///
+    /// # Example
+    /// ```
+    /// # use oxide::definitions::*;
+    /// let bb = (Square::A6).to_bb() | (Square::B7).to_bb();
+    /// assert_eq!(bb.popcnt(), 2);
+    /// ```
+    pub const fn popcnt(&self) -> u32 {
+        self.0.count_ones()
+    }
 }
 
 //------------------------------------------------------------------------------
@@ -512,6 +575,60 @@ pub const FILE_BB: [BB; File::COUNT] = {
     bb
 };
 
+/// The light squares of the board, h1 is a light square.
+///
+/// # Examples
+/// ```
+/// # use oxide::definitions::*;
+/// assert_ne!(Square::H1.to_bb() & LIGHT_SQUARES, BB_EMPTY);
+/// assert_eq!(Square::A1.to_bb() & LIGHT_SQUARES, BB_EMPTY);
+/// ```
+pub const LIGHT_SQUARES: BB = BB(0x55aa_55aa_55aa_55aa);
+
+/// The files either side of each file.
+pub const ADJACENT_FILES_BB: [BB; File::COUNT] = {
+    let mut bb = [BB_EMPTY; File::COUNT];
+    let mut i = 0;
+    while i < File::COUNT {
+        if i > 0 {
+            bb[i].0 |= FILE_BB[i - 1].0;
+        }
+        if i < File::COUNT - 1 {
+            bb[i].0 |= FILE_BB[i + 1].0;
+        }
+        i += 1;
+    }
+    bb
+};
+
+/// All ranks strictly in front of a rank, from the point of view of a side.
+/// the first index is the color, the second index is the rank.
+///
+/// # Examples
+/// ```
+/// # use oxide::definitions::*;
+/// assert_eq!(FORWARD_RANKS_BB[Side::White as usize][Rank::Six as usize], RANK_BB[Rank::Seven as usize] | RANK_BB[Rank::Eight as usize]);
+/// assert_eq!(FORWARD_RANKS_BB[Side::Black as usize][Rank::Two as usize], RANK_BB[Rank::One as usize]);
+/// ```
+pub const FORWARD_RANKS_BB: [[BB; Rank::COUNT]; Side::COUNT] = {
+    let mut bb = [[BB_EMPTY; Rank::COUNT]; Side::COUNT];
+    let mut i = 0;
+    while i < Rank::COUNT {
+        let mut j = 0;
+        while j < Rank::COUNT {
+            if j > i {
+                bb[Side::White as usize][i].0 |= RANK_BB[j].0;
+            }
+            if j < i {
+                bb[Side::Black as usize][i].0 |= RANK_BB[j].0;
+            }
+            j += 1;
+        }
+        i += 1;
+    }
+    bb
+};
+
 pub const DIAGONAL_BB: [BB; Diagonal::COUNT] = {
     let mut bb = [BB_EMPTY; Diagonal::COUNT];
     bb[0] = SQUARE_BB[Square::A8 as usize];
@@ -656,6 +773,42 @@ pub const fn in_between(sq1: Square, sq2: Square) -> BB {
     IN_BETWEEN[sq1 as usize][sq2 as usize]
 }
 
+/// Get the squares attacked by a bishop on `sq`, taking blocking pieces into
+/// account. The blocking squares themselves are included.
+///
+/// # Examples
+/// ```
+/// # use oxide::definitions::*;
+/// let attacks = bishop_attacks(Square::A1, Square::C3.to_bb());
+/// assert_eq!(attacks, Square::B2.to_bb() | Square::C3.to_bb());
+/// ```
+pub fn bishop_attacks(sq: Square, occupied: BB) -> BB {
+    sliding_attacks(sq, BISHOP_ATTACKS[sq as usize], occupied)
+}
+
+/// Get the squares attacked by a rook on `sq`, taking blocking pieces into
+/// account. The blocking squares themselves are included.
+pub fn rook_attacks(sq: Square, occupied: BB) -> BB {
+    sliding_attacks(sq, ROOK_ATTACKS[sq as usize], occupied)
+}
+
+/// Get the squares attacked by a queen on `sq`, taking blocking pieces into
+/// account. The blocking squares themselves are included.
+pub fn queen_attacks(sq: Square, occupied: BB) -> BB {
+    sliding_attacks(sq, QUEEN_ATTACKS[sq as usize], occupied)
+}
+
+fn sliding_attacks(sq: Square, mut targets: BB, occupied: BB) -> BB {
+    let mut attacks = BB_EMPTY;
+    while targets != BB_EMPTY {
+        let to = targets.poplsb();
+        if in_between(sq, to) & occupied == BB_EMPTY {
+            attacks |= to.to_bb();
+        }
+    }
+    attacks
+}
+
 const IN_BETWEEN: [[BB; Square::COUNT]; Square::COUNT] = {
     let mut ret = [[BB_EMPTY; Square::COUNT]; Square::COUNT];
     let mut i = 0;
@@ -0,0 +1,454 @@
+use crate::board::Board;
+use crate::definitions::*;
+use crate::evaluation::PIECE_VALUES;
+use crate::kpk;
+
+use std::collections::HashMap;
+use std::sync::OnceLock;
+use strum::IntoEnumIterator;
+
+/// A score for positions that are won, but where we can't see the mate. This
+/// is well below the mate scores but above any normal evaluation.
+pub const KNOWN_WIN: i32 = 10000;
+
+/// Scale factors are applied to the endgame part of the evaluation, out of
+/// `SCALE_NORMAL`.
+pub const SCALE_NORMAL: i32 = 64;
+pub const SCALE_DRAW: i32 = 0;
+
+/// A specialised evaluation of a known endgame. The score is from the point of
+/// view of the strong side.
+pub type EndgameEvaluator = fn(&Board, Side) -> i32;
+
+/// A material signature, holding the count of each piece in 4 bits.
+pub fn material_key(board: &Board) -> u64 {
+    let mut key = 0;
+    for piece in Piece::iter() {
+        key |= (board.get_piece_bb(piece).popcnt() as u64) << (piece as usize * 4);
+    }
+    key
+}
+
+/// The material key of an endgame described by a code like "KBNK", where the
+/// strong side's pieces come first.
+fn code_to_key(code: &str, strong: Side) -> u64 {
+    let weak_start = code[1..].find('K').unwrap() + 1;
+    let (strong_code, weak_code) = code.split_at(weak_start);
+
+    let mut key = 0;
+    for (pieces, side) in [(strong_code, strong), (weak_code, !strong)] {
+        for c in pieces.chars() {
+            let piece_type = match c {
+                'P' => PieceType::Pawn,
+                'N' => PieceType::Knight,
+                'B' => PieceType::Bishop,
+                'R' => PieceType::Rook,
+                'Q' => PieceType::Queen,
+                'K' => PieceType::King,
+                _ => panic!("Invalid endgame code {code}"),
+            };
+            key += 1 << (Piece::from_type(piece_type, side) as usize * 4);
+        }
+    }
+    key
+}
+
+/// Specialised evaluators indexed by the material key of the position they
+/// apply to, along with the strong side for that key.
+pub struct Endgames {
+    evaluators: HashMap<u64, (EndgameEvaluator, Side)>,
+}
+
+impl Endgames {
+    fn new() -> Endgames {
+        let mut endgames = Endgames {
+            evaluators: HashMap::new(),
+        };
+
+        endgames.add("KBNK", evaluate_kbnk);
+        endgames.add("KPK", evaluate_kpk);
+        endgames.add("KRKP", evaluate_krkp);
+        endgames.add("KQKP", evaluate_kqkp);
+        endgames.add("KRKB", evaluate_krkb);
+        endgames.add("KRKN", evaluate_krkn);
+        endgames
+    }
+
+    fn add(&mut self, code: &str, evaluator: EndgameEvaluator) {
+        for strong in [Side::White, Side::Black] {
+            self.evaluators
+                .insert(code_to_key(code, strong), (evaluator, strong));
+        }
+    }
+
+    /// Look up a specialised evaluator for the board's material.
+    pub fn probe(&self, material_key: u64) -> Option<(EndgameEvaluator, Side)> {
+        self.evaluators.get(&material_key).copied()
+    }
+}
+
+/// The global registry of specialised endgame evaluators.
+pub fn endgames() -> &'static Endgames {
+    static ENDGAMES: OnceLock<Endgames> = OnceLock::new();
+    ENDGAMES.get_or_init(Endgames::new)
+}
+
+/// Evaluate the board with a specialised evaluator if there is one for its
+/// material, from white's point of view.
+pub fn evaluate_endgame(board: &Board, material_key: u64) -> Option<i32> {
+    let (evaluator, strong) = endgames().probe(material_key)?;
+    let score = evaluator(board, strong);
+    Some(match strong {
+        Side::White => score,
+        Side::Black => -score,
+    })
+}
+
+/// Neither side has enough material to ever deliver checkmate.
+pub fn insufficient_material(board: &Board) -> bool {
+    let heavy = board.get_piece_bb(Piece::WhitePawn)
+        | board.get_piece_bb(Piece::BlackPawn)
+        | board.get_piece_bb(Piece::WhiteRook)
+        | board.get_piece_bb(Piece::BlackRook)
+        | board.get_piece_bb(Piece::WhiteQueen)
+        | board.get_piece_bb(Piece::BlackQueen);
+    if heavy != BB_EMPTY {
+        return false;
+    }
+
+    let knights = board.get_piece_bb(Piece::WhiteKnight) | board.get_piece_bb(Piece::BlackKnight);
+    let bishops = board.get_piece_bb(Piece::WhiteBishop) | board.get_piece_bb(Piece::BlackBishop);
+
+    // a lone minor piece, or any number of bishops all on the same colour
+    (knights | bishops).popcnt() <= 1
+        || (knights == BB_EMPTY
+            && (bishops & LIGHT_SQUARES == BB_EMPTY || bishops & !LIGHT_SQUARES == BB_EMPTY))
+}
+
+/// Scale down the endgame score in positions that are drawish despite one side
+/// having an advantage. `strong` is the side the endgame score favours.
+pub fn scale_factor(board: &Board, strong: Side) -> i32 {
+    let weak = !strong;
+    let strong_pawns = board.get_piece_bb(Piece::from_type(PieceType::Pawn, strong));
+    let strong_npm = non_pawn_material(board, strong);
+    let weak_npm = non_pawn_material(board, weak);
+    let bishop = PIECE_VALUES[PieceType::Bishop as usize].mg();
+    let rook = PIECE_VALUES[PieceType::Rook as usize].mg();
+
+    // without pawns, a small material edge is rarely enough to win
+    if strong_pawns == BB_EMPTY && strong_npm - weak_npm <= bishop {
+        return if strong_npm < rook {
+            SCALE_DRAW
+        } else if weak_npm <= bishop {
+            4
+        } else {
+            14
+        };
+    }
+
+    if is_wrong_bishop_draw(board, strong) {
+        return SCALE_DRAW;
+    }
+
+    let white_bishops = board.get_piece_bb(Piece::WhiteBishop);
+    let black_bishops = board.get_piece_bb(Piece::BlackBishop);
+    if white_bishops.popcnt() == 1
+        && black_bishops.popcnt() == 1
+        && ((white_bishops & LIGHT_SQUARES == BB_EMPTY)
+            != (black_bishops & LIGHT_SQUARES == BB_EMPTY))
+    {
+        // opposite coloured bishops are very drawish, less so with other pieces
+        let bishop_value = PIECE_VALUES[PieceType::Bishop as usize].mg();
+        return if strong_npm == bishop_value && weak_npm == bishop_value {
+            22
+        } else {
+            46
+        };
+    }
+
+    SCALE_NORMAL
+}
+
+/// King, bishop and rook pawns, where the bishop does not control the
+/// promotion square and the defending king sits in front of the pawns.
+fn is_wrong_bishop_draw(board: &Board, strong: Side) -> bool {
+    let weak = !strong;
+    let pawns = board.get_piece_bb(Piece::from_type(PieceType::Pawn, strong));
+    let bishops = board.get_piece_bb(Piece::from_type(PieceType::Bishop, strong));
+
+    if non_pawn_material(board, strong) != PIECE_VALUES[PieceType::Bishop as usize].mg()
+        || bishops.popcnt() != 1
+        || pawns == BB_EMPTY
+    {
+        return false;
+    }
+
+    let file = if pawns & !FILE_BB[File::A as usize] == BB_EMPTY {
+        File::A
+    } else if pawns & !FILE_BB[File::H as usize] == BB_EMPTY {
+        File::H
+    } else {
+        return false;
+    };
+
+    let promotion = match strong {
+        Side::White => Square::from_coord(file, Rank::Eight),
+        Side::Black => Square::from_coord(file, Rank::One),
+    };
+
+    let bishop_on_light = bishops & LIGHT_SQUARES != BB_EMPTY;
+    let promotion_on_light = promotion.to_bb() & LIGHT_SQUARES != BB_EMPTY;
+
+    bishop_on_light != promotion_on_light && board.get_king(weak).distance(promotion) <= 1
+}
+
+fn non_pawn_material(board: &Board, s: Side) -> i32 {
+    [
+        PieceType::Knight,
+        PieceType::Bishop,
+        PieceType::Rook,
+        PieceType::Queen,
+    ]
+    .iter()
+    .map(|&t| {
+        PIECE_VALUES[t as usize].mg() * board.get_piece_bb(Piece::from_type(t, s)).popcnt() as i32
+    })
+    .sum()
+}
+
+// Drive the king towards the edge of the board
+fn push_to_edge(sq: Square) -> i32 {
+    let file = sq.file() as i32;
+    let rank = sq.rank() as i32;
+    let centre_distance = (3 - file).max(file - 4) + (3 - rank).max(rank - 4);
+    20 + 10 * centre_distance
+}
+
+// Bring two pieces closer together, or keep them apart
+fn push_close(a: Square, b: Square) -> i32 {
+    140 - 20 * a.distance(b) as i32
+}
+
+fn push_away(a: Square, b: Square) -> i32 {
+    120 - push_close(a, b)
+}
+
+/// Mate with bishop and knight is only possible in a corner of the bishop's
+/// colour, so we drive the defending king there.
+fn evaluate_kbnk(board: &Board, strong: Side) -> i32 {
+    let strong_king = board.get_king(strong);
+    let weak_king = board.get_king(!strong);
+    let bishop = board.get_piece_bb(Piece::from_type(PieceType::Bishop, strong));
+
+    let corners = if bishop & LIGHT_SQUARES != BB_EMPTY {
+        [Square::A8, Square::H1]
+    } else {
+        [Square::A1, Square::H8]
+    };
+    let corner_distance = corners
+        .iter()
+        .map(|&c| weak_king.distance(c))
+        .min()
+        .unwrap() as i32;
+
+    KNOWN_WIN + push_close(strong_king, weak_king) + 50 * (7 - corner_distance)
+}
+
+/// King and pawn against king is looked up in the bitbase.
+fn evaluate_kpk(board: &Board, strong: Side) -> i32 {
+    if kpk::probe(board) != Some(true) {
+        return 0;
+    }
+
+    let pawn = board
+        .get_piece_bb(Piece::from_type(PieceType::Pawn, strong))
+        .ctz();
+    KNOWN_WIN + PIECE_VALUES[PieceType::Pawn as usize].eg() + pawn.relative_rank(strong) as i32
+}
+
+/// Rook against pawn is usually a win, unless the pawn is far advanced and
+/// supported by its king while the strong king is far away.
+fn evaluate_krkp(board: &Board, strong: Side) -> i32 {
+    let weak = !strong;
+    let strong_king = board.get_king(strong);
+    let weak_king = board.get_king(weak);
+    let rook = board
+        .get_piece_bb(Piece::from_type(PieceType::Rook, strong))
+        .ctz();
+    let pawn = board
+        .get_piece_bb(Piece::from_type(PieceType::Pawn, weak))
+        .ctz();
+
+    let (push, queening) = match weak {
+        Side::White => (8, Square::from_coord(pawn.file(), Rank::Eight)),
+        Side::Black => (-8, Square::from_coord(pawn.file(), Rank::One)),
+    };
+    let rook_value = PIECE_VALUES[PieceType::Rook as usize].eg();
+    let weak_tempo = if board.stm == weak { 1 } else { 0 };
+    let strong_tempo = 1 - weak_tempo;
+
+    // the strong king stands in front of the pawn
+    if in_between(pawn, queening) & strong_king.to_bb() != BB_EMPTY || strong_king == queening {
+        return rook_value - strong_king.distance(pawn) as i32;
+    }
+
+    // the weak king is too far from the pawn and the rook to protect either
+    if weak_king.distance(pawn) >= 3 + weak_tempo && weak_king.distance(rook) >= 3 {
+        return rook_value - strong_king.distance(pawn) as i32;
+    }
+
+    // the pawn is far advanced and supported, and the strong king is far away
+    if weak_king.relative_rank(weak) as usize >= Rank::Six as usize
+        && weak_king.distance(pawn) == 1
+        && strong_king.relative_rank(weak) as usize <= Rank::Five as usize
+        && strong_king.distance(pawn) > 2 + strong_tempo
+    {
+        return 80 - 8 * strong_king.distance(pawn) as i32;
+    }
+
+    let stop = Square::from_index((pawn as i32 + push) as usize);
+    200 - 8
+        * (strong_king.distance(stop) as i32
+            - weak_king.distance(stop) as i32
+            - pawn.distance(queening) as i32)
+}
+
+/// Queen against pawn is a win, except against a bishop or rook pawn on the
+/// seventh rank supported by its king, where stalemate tricks save the game.
+fn evaluate_kqkp(board: &Board, strong: Side) -> i32 {
+    let weak = !strong;
+    let strong_king = board.get_king(strong);
+    let weak_king = board.get_king(weak);
+    let pawn = board
+        .get_piece_bb(Piece::from_type(PieceType::Pawn, weak))
+        .ctz();
+
+    let mut score = push_close(strong_king, weak_king);
+
+    let drawish_file = matches!(pawn.file(), File::A | File::C | File::F | File::H);
+    if pawn.relative_rank(weak) != Rank::Seven || weak_king.distance(pawn) != 1 || !drawish_file {
+        score += PIECE_VALUES[PieceType::Queen as usize].eg()
+            - PIECE_VALUES[PieceType::Pawn as usize].eg();
+    }
+
+    score
+}
+
+/// Rook against bishop is generally a draw, the rook side can only hope to
+/// drive the king to the edge.
+fn evaluate_krkb(board: &Board, strong: Side) -> i32 {
+    push_to_edge(board.get_king(!strong))
+}
+
+/// Rook against knight is generally a draw, though separating the knight from
+/// its king gives some winning chances.
+fn evaluate_krkn(board: &Board, strong: Side) -> i32 {
+    let weak_king = board.get_king(!strong);
+    let knight = board
+        .get_piece_bb(Piece::from_type(PieceType::Knight, !strong))
+        .ctz();
+    push_to_edge(weak_king) + push_away(weak_king, knight)
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::{endgame::*, fen};
+
+    fn board(fen: &str) -> Board {
+        fen::fen_to_board(fen).unwrap()
+    }
+
+    fn endgame_score(fen: &str) -> i32 {
+        let board = board(fen);
+        evaluate_endgame(&board, material_key(&board)).unwrap()
+    }
+
+    #[test]
+    fn registry() {
+        let b = board("8/8/8/3k4/8/8/8/KBN5 w - - 0 1");
+        assert!(endgames().probe(material_key(&b)).is_some());
+        assert_eq!(material_key(&b), code_to_key("KBNK", Side::White));
+
+        let b = board("kbn5/8/8/3K4/8/8/8/8 w - - 0 1");
+        assert!(endgames().probe(material_key(&b)).unwrap().1 == Side::Black);
+
+        let b = board("8/8/8/3k4/8/8/8/KBB5 w - - 0 1");
+        assert!(endgames().probe(material_key(&b)).is_none());
+    }
+
+    #[test]
+    fn kbnk() {
+        // dark squared bishop, the weak king should be driven to a1 or h8
+        let right_corner = endgame_score("7k/8/5K2/8/8/8/8/2B1N3 w - - 0 1");
+        let wrong_corner = endgame_score("k7/8/2K5/8/8/8/8/2B1N3 w - - 0 1");
+        assert!(right_corner > wrong_corner);
+        assert!(wrong_corner > KNOWN_WIN);
+
+        let mirrored = endgame_score("2b1n3/8/8/8/8/5k2/8/7K b - - 0 1");
+        assert_eq!(mirrored, -right_corner);
+    }
+
+    #[test]
+    fn kpk() {
+        assert!(endgame_score("8/8/3k4/8/3K4/3P4/8/8 b - - 0 1") > KNOWN_WIN);
+        assert_eq!(endgame_score("8/8/3k4/8/3K4/3P4/8/8 w - - 0 1"), 0);
+        assert!(endgame_score("8/8/8/3p4/3k4/8/3K4/8 w - - 0 1") < -KNOWN_WIN);
+    }
+
+    #[test]
+    fn krkp_and_kqkp() {
+        // the white king stops the pawn
+        assert!(endgame_score("8/8/8/8/1k6/1p6/8/1K5R w - - 0 1") > 400);
+        // the pawn is about to queen with its king, and the white king is far away
+        assert!(endgame_score("K7/8/8/8/8/8/1pk5/7R b - - 0 1") < 100);
+
+        // a rook pawn on the seventh supported by its king is drawish
+        assert!(endgame_score("8/8/8/8/8/1K6/pk6/7Q w - - 0 1") < 200);
+        assert!(endgame_score("8/8/8/8/8/2K5/1pk5/7Q w - - 0 1") > 500);
+    }
+
+    #[test]
+    fn scaling() {
+        // opposite coloured bishops
+        let b = board("8/4kb2/8/8/4P3/8/3BK3/8 w - - 0 1");
+        assert!(scale_factor(&b, Side::White) < SCALE_NORMAL);
+
+        // bishops of the same colour
+        let b = board("8/4k1b1/8/8/4P3/8/3BK3/8 w - - 0 1");
+        assert_eq!(scale_factor(&b, Side::White), SCALE_NORMAL);
+
+        // wrong bishop with the defending king in the corner
+        let b = board("k7/8/8/P7/8/8/3B4/4K3 w - - 0 1");
+        assert_eq!(scale_factor(&b, Side::White), SCALE_DRAW);
+        let b = board("k7/8/8/P7/8/8/2B5/4K3 w - - 0 1");
+        assert_eq!(scale_factor(&b, Side::White), SCALE_NORMAL);
+
+        // pawnless with a small edge
+        let b = board("8/4k3/8/8/8/8/3NK3/8 w - - 0 1");
+        assert_eq!(scale_factor(&b, Side::White), SCALE_DRAW);
+        let b = board("8/3nk3/8/8/8/8/3RK3/8 w - - 0 1");
+        assert!(scale_factor(&b, Side::White) < SCALE_NORMAL);
+    }
+
+    #[test]
+    fn insufficient() {
+        let drawn = [
+            "8/4k3/8/8/8/8/4K3/8 w - - 0 1",
+            "8/4k3/8/8/8/8/3NK3/8 w - - 0 1",
+            // bishops on the same colour
+            "8/4k1b1/8/8/8/8/3BK3/8 w - - 0 1",
+        ];
+        for fen in drawn {
+            assert!(insufficient_material(&board(fen)), "{fen}");
+        }
+
+        let not_drawn = [
+            "8/4kb2/8/8/8/8/3BK3/8 w - - 0 1",
+            "8/4k3/8/8/8/8/2NNK3/8 w - - 0 1",
+            "8/4k3/8/8/8/8/3PK3/8 w - - 0 1",
+        ];
+        for fen in not_drawn {
+            assert!(!insufficient_material(&board(fen)), "{fen}");
+        }
+    }
+}
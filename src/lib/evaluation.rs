@@ -0,0 +1,841 @@
+use crate::board::Board;
+use crate::definitions::*;
+use crate::endgame;
+use crate::fen;
+use crate::pawns::{self, PawnEntry, PawnHashTable, PAWN_ATTACK_SPAN};
+use crate::trace::{EvalTrace, NoTrace, Param, Trace};
+
+use strum::{EnumCount, IntoEnumIterator};
+
+/// A pair of middlegame and endgame scores, which are blended together
+/// according to the game phase once the evaluation is complete.
+#[derive(Copy, Clone, Debug, Default, PartialEq)]
+pub struct Score(pub i32, pub i32);
+
+impl Score {
+    pub const fn mg(&self) -> i32 {
+        self.0
+    }
+
+    pub const fn eg(&self) -> i32 {
+        self.1
+    }
+}
+
+impl std::ops::Add for Score {
+    type Output = Self;
+
+    fn add(self, rhs: Self) -> Self::Output {
+        Self(self.0 + rhs.0, self.1 + rhs.1)
+    }
+}
+
+impl std::ops::Sub for Score {
+    type Output = Self;
+
+    fn sub(self, rhs: Self) -> Self::Output {
+        Self(self.0 - rhs.0, self.1 - rhs.1)
+    }
+}
+
+impl std::ops::AddAssign for Score {
+    fn add_assign(&mut self, rhs: Self) {
+        self.0 += rhs.0;
+        self.1 += rhs.1;
+    }
+}
+
+impl std::ops::SubAssign for Score {
+    fn sub_assign(&mut self, rhs: Self) {
+        self.0 -= rhs.0;
+        self.1 -= rhs.1;
+    }
+}
+
+impl std::ops::Neg for Score {
+    type Output = Self;
+
+    fn neg(self) -> Self::Output {
+        Self(-self.0, -self.1)
+    }
+}
+
+impl std::ops::Mul<i32> for Score {
+    type Output = Self;
+
+    fn mul(self, rhs: i32) -> Self::Output {
+        Self(self.0 * rhs, self.1 * rhs)
+    }
+}
+
+/// Material values indexed by `PieceType`.
+pub const PIECE_VALUES: [Score; PieceType::COUNT] = [
+    Score(82, 94),
+    Score(337, 281),
+    Score(365, 297),
+    Score(477, 512),
+    Score(1025, 936),
+    Score(0, 0),
+];
+
+// Contribution of each piece type to the game phase, indexed by `PieceType`
+const PHASE_WEIGHTS: [i32; PieceType::COUNT] = [0, 1, 1, 2, 4, 0];
+
+/// The phase of the starting position, and of any position with at least that
+/// much material.
+pub const MAX_PHASE: i32 = 24;
+
+// Mobility bonuses indexed by the number of safe squares the piece attacks
+pub const KNIGHT_MOBILITY: [Score; 9] = [
+    Score(-31, -40),
+    Score(-26, -28),
+    Score(-6, -15),
+    Score(-2, -8),
+    Score(2, 3),
+    Score(7, 6),
+    Score(11, 9),
+    Score(14, 10),
+    Score(17, 12),
+];
+pub const BISHOP_MOBILITY: [Score; 14] = [
+    Score(-24, -30),
+    Score(-10, -12),
+    Score(8, -2),
+    Score(13, 6),
+    Score(19, 12),
+    Score(25, 21),
+    Score(28, 27),
+    Score(31, 29),
+    Score(32, 32),
+    Score(34, 36),
+    Score(40, 39),
+    Score(41, 43),
+    Score(45, 44),
+    Score(49, 48),
+];
+pub const ROOK_MOBILITY: [Score; 15] = [
+    Score(-30, -39),
+    Score(-10, -9),
+    Score(1, 11),
+    Score(2, 20),
+    Score(2, 35),
+    Score(6, 50),
+    Score(11, 52),
+    Score(16, 60),
+    Score(20, 67),
+    Score(20, 70),
+    Score(21, 79),
+    Score(24, 82),
+    Score(28, 84),
+    Score(29, 85),
+    Score(31, 86),
+];
+pub const QUEEN_MOBILITY: [Score; 28] = [
+    Score(-15, -24),
+    Score(-6, -15),
+    Score(-4, -4),
+    Score(-4, 10),
+    Score(10, 20),
+    Score(12, 28),
+    Score(12, 30),
+    Score(17, 37),
+    Score(19, 39),
+    Score(26, 48),
+    Score(32, 48),
+    Score(33, 50),
+    Score(33, 60),
+    Score(33, 64),
+    Score(34, 66),
+    Score(34, 67),
+    Score(36, 68),
+    Score(36, 70),
+    Score(38, 74),
+    Score(40, 75),
+    Score(46, 76),
+    Score(54, 84),
+    Score(54, 84),
+    Score(54, 86),
+    Score(55, 91),
+    Score(57, 91),
+    Score(57, 96),
+    Score(58, 110),
+];
+
+// King safety. Each enemy piece attacking the king zone adds its weight to the
+// king's danger, but only once there are at least two attackers.
+pub const KING_ATTACKER_WEIGHT: [Score; PieceType::COUNT] = [
+    Score(0, 0),
+    Score(-16, -2),
+    Score(-12, -2),
+    Score(-18, -4),
+    Score(-30, -6),
+    Score(0, 0),
+];
+pub const KING_ZONE_ATTACK: Score = Score(-6, -1);
+pub const SAFE_CHECK: [Score; PieceType::COUNT] = [
+    Score(0, 0),
+    Score(-40, -8),
+    Score(-25, -6),
+    Score(-45, -10),
+    Score(-35, -8),
+    Score(0, 0),
+];
+// indexed by the relative rank of our closest pawn in front of the king on each
+// of the three files around it, or zero if there is none
+pub const PAWN_SHELTER: [Score; Rank::COUNT] = [
+    Score(-18, -4),
+    Score(20, 0),
+    Score(12, 0),
+    Score(-2, 0),
+    Score(-5, 0),
+    Score(-8, 0),
+    Score(-10, 0),
+    Score(0, 0),
+];
+// indexed by the relative rank of the closest enemy pawn in front of the king
+// on each of the three files around it, or zero if there is none
+pub const PAWN_STORM: [Score; Rank::COUNT] = [
+    Score(0, 0),
+    Score(-10, -2),
+    Score(-30, -5),
+    Score(-15, -3),
+    Score(-6, 0),
+    Score(-2, 0),
+    Score(0, 0),
+    Score(0, 0),
+];
+
+// Threats, indexed by the type of the piece being attacked
+pub const THREAT_BY_PAWN: [Score; PieceType::COUNT] = [
+    Score(0, 0),
+    Score(70, 40),
+    Score(70, 40),
+    Score(90, 50),
+    Score(80, 40),
+    Score(0, 0),
+];
+pub const THREAT_BY_MINOR: [Score; PieceType::COUNT] = [
+    Score(0, 0),
+    Score(0, 0),
+    Score(0, 0),
+    Score(40, 60),
+    Score(40, 60),
+    Score(0, 0),
+];
+pub const HANGING_PIECE: Score = Score(30, 15);
+
+pub const ROOK_OPEN_FILE: Score = Score(22, 10);
+pub const ROOK_SEMI_OPEN_FILE: Score = Score(10, 5);
+pub const ROOK_ON_SEVENTH: Score = Score(10, 25);
+pub const BISHOP_PAIR: Score = Score(25, 55);
+pub const KNIGHT_OUTPOST: Score = Score(25, 10);
+pub const BISHOP_OUTPOST: Score = Score(15, 5);
+
+/// The game phase from `MAX_PHASE` (opening) down to 0 (bare kings and pawns).
+pub fn phase(board: &Board) -> i32 {
+    let mut phase = 0;
+    for piece in Piece::iter() {
+        phase +=
+            PHASE_WEIGHTS[piece.get_type() as usize] * board.get_piece_bb(piece).popcnt() as i32;
+    }
+    phase.min(MAX_PHASE)
+}
+
+/// Blend the middlegame and endgame scores according to the game phase.
+pub const fn taper(score: Score, phase: i32) -> i32 {
+    (score.0 * phase + score.1 * (MAX_PHASE - phase)) / MAX_PHASE
+}
+
+/// The material balance from white's point of view.
+pub fn material(board: &Board) -> Score {
+    evaluate_material(board, Side::White, &mut NoTrace)
+        - evaluate_material(board, Side::Black, &mut NoTrace)
+}
+
+fn evaluate_material<T: Trace>(board: &Board, us: Side, trace: &mut T) -> Score {
+    let mut score = Score::default();
+    for piece_type in PieceType::iter() {
+        let count = board
+            .get_piece_bb(Piece::from_type(piece_type, us))
+            .popcnt() as i32;
+        score += PIECE_VALUES[piece_type as usize] * count;
+        trace.add(Param::PieceValues, piece_type as usize, us, count);
+    }
+    score
+}
+
+/// Attack information gathered while evaluating the pieces, which the king
+/// safety and threat terms then use.
+struct EvalInfo {
+    attacked_by: [[BB; PieceType::COUNT]; Side::COUNT],
+    attacked: [BB; Side::COUNT],
+    // squares the side's pieces can move to without being chased off by a pawn
+    mobility_area: [BB; Side::COUNT],
+    // squares around the side's king
+    king_zone: [BB; Side::COUNT],
+    // attacks by the side's pieces on the enemy king zone
+    king_attackers: [[i32; PieceType::COUNT]; Side::COUNT],
+    king_zone_attacks: [i32; Side::COUNT],
+}
+
+impl EvalInfo {
+    fn new(board: &Board) -> EvalInfo {
+        let mut info = EvalInfo {
+            attacked_by: [[BB_EMPTY; PieceType::COUNT]; Side::COUNT],
+            attacked: [BB_EMPTY; Side::COUNT],
+            mobility_area: [BB_EMPTY; Side::COUNT],
+            king_zone: [BB_EMPTY; Side::COUNT],
+            king_attackers: [[0; PieceType::COUNT]; Side::COUNT],
+            king_zone_attacks: [0; Side::COUNT],
+        };
+
+        for us in [Side::White, Side::Black] {
+            let king = board.get_king(us);
+            let mut pawn_attacks = BB_EMPTY;
+            let mut pawns = board.get_piece_bb(Piece::from_type(PieceType::Pawn, us));
+            while pawns != BB_EMPTY {
+                pawn_attacks |= PAWN_ATTACKS[us as usize][pawns.poplsb() as usize];
+            }
+
+            info.attacked_by[us as usize][PieceType::Pawn as usize] = pawn_attacks;
+            info.attacked_by[us as usize][PieceType::King as usize] = KING_ATTACKS[king as usize];
+            info.attacked[us as usize] = pawn_attacks | KING_ATTACKS[king as usize];
+            info.king_zone[us as usize] = KING_ATTACKS[king as usize] | king.to_bb();
+        }
+
+        for us in [Side::White, Side::Black] {
+            let our_pawns = board.get_piece_bb(Piece::from_type(PieceType::Pawn, us));
+            let our_king = board.get_piece_bb(Piece::from_type(PieceType::King, us));
+            info.mobility_area[us as usize] =
+                !(our_pawns | our_king | info.attacked_by[!us as usize][PieceType::Pawn as usize]);
+        }
+
+        info
+    }
+}
+
+/// Mobility, outposts, rooks on open files and the bishop pair for one side.
+/// Also fills in the attack information for the side's pieces.
+fn evaluate_pieces<T: Trace>(board: &Board, info: &mut EvalInfo, us: Side, trace: &mut T) -> Score {
+    let mut score = Score::default();
+    let occupied = board.occupied_squares();
+    let our_pawns = board.get_piece_bb(Piece::from_type(PieceType::Pawn, us));
+    let their_pawns = board.get_piece_bb(Piece::from_type(PieceType::Pawn, !us));
+    let their_king = board.get_king(!us);
+
+    for piece_type in [
+        PieceType::Knight,
+        PieceType::Bishop,
+        PieceType::Rook,
+        PieceType::Queen,
+    ] {
+        let mut pieces = board.get_piece_bb(Piece::from_type(piece_type, us));
+        while pieces != BB_EMPTY {
+            let sq = pieces.poplsb();
+            let attacks = match piece_type {
+                PieceType::Knight => KNIGHT_ATTACKS[sq as usize],
+                PieceType::Bishop => bishop_attacks(sq, occupied),
+                PieceType::Rook => rook_attacks(sq, occupied),
+                _ => queen_attacks(sq, occupied),
+            };
+
+            info.attacked_by[us as usize][piece_type as usize] |= attacks;
+            info.attacked[us as usize] |= attacks;
+
+            let zone_attacks = attacks & info.king_zone[!us as usize];
+            if zone_attacks != BB_EMPTY {
+                info.king_attackers[us as usize][piece_type as usize] += 1;
+                info.king_zone_attacks[us as usize] += zone_attacks.popcnt() as i32;
+            }
+
+            let mobility = (attacks & info.mobility_area[us as usize]).popcnt() as usize;
+            let (table, param) = match piece_type {
+                PieceType::Knight => (&KNIGHT_MOBILITY[..], Param::KnightMobility),
+                PieceType::Bishop => (&BISHOP_MOBILITY[..], Param::BishopMobility),
+                PieceType::Rook => (&ROOK_MOBILITY[..], Param::RookMobility),
+                _ => (&QUEEN_MOBILITY[..], Param::QueenMobility),
+            };
+            score += table[mobility];
+            trace.add(param, mobility, us, 1);
+
+            // an outpost is a square on the enemy's half of the board, supported
+            // by a pawn, that enemy pawns can never attack
+            let rank = sq.relative_rank(us) as usize;
+            let outpost = (3..=5).contains(&rank)
+                && info.attacked_by[us as usize][PieceType::Pawn as usize] & sq.to_bb() != BB_EMPTY
+                && PAWN_ATTACK_SPAN[us as usize][sq as usize] & their_pawns == BB_EMPTY;
+
+            match piece_type {
+                PieceType::Knight if outpost => {
+                    score += KNIGHT_OUTPOST;
+                    trace.add(Param::KnightOutpost, 0, us, 1);
+                }
+                PieceType::Bishop if outpost => {
+                    score += BISHOP_OUTPOST;
+                    trace.add(Param::BishopOutpost, 0, us, 1);
+                }
+                PieceType::Rook => {
+                    let file = FILE_BB[sq.file() as usize];
+                    if file & our_pawns == BB_EMPTY {
+                        if file & their_pawns == BB_EMPTY {
+                            score += ROOK_OPEN_FILE;
+                            trace.add(Param::RookOpenFile, 0, us, 1);
+                        } else {
+                            score += ROOK_SEMI_OPEN_FILE;
+                            trace.add(Param::RookSemiOpenFile, 0, us, 1);
+                        }
+                    }
+
+                    let seventh = match us {
+                        Side::White => RANK_BB[Rank::Seven as usize],
+                        Side::Black => RANK_BB[Rank::Two as usize],
+                    };
+                    if rank == Rank::Seven as usize
+                        && (their_king.relative_rank(us) == Rank::Eight
+                            || their_pawns & seventh != BB_EMPTY)
+                    {
+                        score += ROOK_ON_SEVENTH;
+                        trace.add(Param::RookOnSeventh, 0, us, 1);
+                    }
+                }
+                _ => (),
+            }
+        }
+    }
+
+    if board
+        .get_piece_bb(Piece::from_type(PieceType::Bishop, us))
+        .popcnt()
+        >= 2
+    {
+        score += BISHOP_PAIR;
+        trace.add(Param::BishopPair, 0, us, 1);
+    }
+
+    score
+}
+
+/// Pawn shelter, pawn storms and the enemy attacks on the king of one side.
+fn evaluate_king_safety<T: Trace>(
+    board: &Board,
+    info: &EvalInfo,
+    us: Side,
+    trace: &mut T,
+) -> Score {
+    let mut score = Score::default();
+    let them = !us;
+    let king = board.get_king(us);
+    let our_pawns = board.get_piece_bb(Piece::from_type(PieceType::Pawn, us));
+    let their_pawns = board.get_piece_bb(Piece::from_type(PieceType::Pawn, them));
+
+    // look at the king file and the files either side, moving the centre off
+    // the edge so we always consider three files
+    let centre = (king.file() as usize).clamp(File::B as usize, File::G as usize);
+    let in_front =
+        FORWARD_RANKS_BB[us as usize][king.rank() as usize] | RANK_BB[king.rank() as usize];
+    for file in &FILE_BB[centre - 1..=centre + 1] {
+        let ours = closest_relative_rank(*file & in_front & our_pawns, us);
+        let theirs = closest_relative_rank(*file & in_front & their_pawns, us);
+        score += PAWN_SHELTER[ours];
+        score += PAWN_STORM[theirs];
+        trace.add(Param::PawnShelter, ours, us, 1);
+        trace.add(Param::PawnStorm, theirs, us, 1);
+    }
+
+    let attackers = &info.king_attackers[them as usize];
+    if attackers.iter().sum::<i32>() >= 2 {
+        for (piece_type, &count) in attackers.iter().enumerate() {
+            score += KING_ATTACKER_WEIGHT[piece_type] * count;
+            trace.add(Param::KingAttackerWeight, piece_type, us, count);
+        }
+
+        let zone_attacks = info.king_zone_attacks[them as usize];
+        score += KING_ZONE_ATTACK * zone_attacks;
+        trace.add(Param::KingZoneAttack, 0, us, zone_attacks);
+    }
+
+    // squares the enemy could check from, which we do not defend
+    let occupied = board.occupied_squares();
+    let safe = !info.attacked[us as usize] & !board.get_pieces(them);
+    let checks = [
+        (PieceType::Knight, KNIGHT_ATTACKS[king as usize]),
+        (PieceType::Bishop, bishop_attacks(king, occupied)),
+        (PieceType::Rook, rook_attacks(king, occupied)),
+        (PieceType::Queen, queen_attacks(king, occupied)),
+    ];
+    for (piece_type, squares) in checks {
+        let safe_checks = squares & safe & info.attacked_by[them as usize][piece_type as usize];
+        score += SAFE_CHECK[piece_type as usize] * safe_checks.popcnt() as i32;
+        trace.add(
+            Param::SafeCheck,
+            piece_type as usize,
+            us,
+            safe_checks.popcnt() as i32,
+        );
+    }
+
+    score
+}
+
+// The relative rank of the pawn closest to our side of the board, or zero
+fn closest_relative_rank(pawns: BB, us: Side) -> usize {
+    if pawns == BB_EMPTY {
+        return 0;
+    }
+
+    match us {
+        Side::White => pawns.ctz().relative_rank(us) as usize,
+        Side::Black => {
+            Square::from_index(63 - pawns.0.leading_zeros() as usize).relative_rank(us) as usize
+        }
+    }
+}
+
+/// Threats made by one side against the other side's pieces.
+fn evaluate_threats<T: Trace>(board: &Board, info: &EvalInfo, us: Side, trace: &mut T) -> Score {
+    let mut score = Score::default();
+    let them = !us;
+
+    let their_pawns = board.get_piece_bb(Piece::from_type(PieceType::Pawn, them));
+    let their_king = board.get_piece_bb(Piece::from_type(PieceType::King, them));
+    let their_pieces = board.get_pieces(them) & !their_pawns & !their_king;
+
+    let minor_attacks = info.attacked_by[us as usize][PieceType::Knight as usize]
+        | info.attacked_by[us as usize][PieceType::Bishop as usize];
+
+    let mut targets = their_pieces;
+    while targets != BB_EMPTY {
+        let sq = targets.poplsb();
+        let piece_type = board.get_square(sq).unwrap().get_type() as usize;
+
+        if info.attacked_by[us as usize][PieceType::Pawn as usize] & sq.to_bb() != BB_EMPTY {
+            score += THREAT_BY_PAWN[piece_type];
+            trace.add(Param::ThreatByPawn, piece_type, us, 1);
+        }
+
+        if minor_attacks & sq.to_bb() != BB_EMPTY {
+            score += THREAT_BY_MINOR[piece_type];
+            trace.add(Param::ThreatByMinor, piece_type, us, 1);
+        }
+
+        if info.attacked[us as usize] & !info.attacked[them as usize] & sq.to_bb() != BB_EMPTY {
+            score += HANGING_PIECE;
+            trace.add(Param::HangingPiece, 0, us, 1);
+        }
+    }
+
+    score
+}
+
+/// Evaluate the board from the point of view of the side to move, in
+/// centipawns. Dead drawn positions score 0, and known endgames are handed to
+/// their specialised evaluators.
+pub fn evaluate(board: &Board, pawn_table: &mut PawnHashTable) -> i32 {
+    if endgame::insufficient_material(board) {
+        return 0;
+    }
+
+    let eval = match endgame::evaluate_endgame(board, endgame::material_key(board)) {
+        Some(eval) => eval,
+        None => {
+            let pawn_entry = pawn_table.probe(board);
+            let score = evaluate_terms(board, &pawn_entry, &mut NoTrace);
+            taper(scale(score, scale_factor(board, score)), phase(board))
+        }
+    };
+
+    match board.stm {
+        Side::White => eval,
+        Side::Black => -eval,
+    }
+}
+
+/// Evaluate the board like `evaluate`, recording the contribution of every
+/// term for each side. The pawn hash table is bypassed so that the pawn terms
+/// are traced as well. The result is deterministic, and its `Display` is a
+/// table of the terms. Boards without exactly one king for each side are
+/// rejected.
+pub fn eval_trace(board: &Board) -> Result<EvalTrace, String> {
+    if !board.has_one_king_each() {
+        return Err(format!(
+            "Cannot evaluate {}: each side needs exactly one king",
+            fen::board_to_fen(board)
+        ));
+    }
+
+    let mut trace = EvalTrace::new();
+    let score = trace_terms(board, &mut trace);
+
+    trace.phase = phase(board);
+    trace.scale = scale_factor(board, score);
+
+    trace.endgame = if endgame::insufficient_material(board) {
+        Some(0)
+    } else {
+        endgame::evaluate_endgame(board, endgame::material_key(board))
+    };
+    trace.eval = match trace.endgame {
+        Some(eval) => eval,
+        None => taper(scale(score, trace.scale), trace.phase),
+    };
+
+    Ok(trace)
+}
+
+/// The sum of all the terms of the general evaluation from white's point of
+/// view, before scaling and tapering, passing every parameter used to `trace`.
+pub fn trace_terms<T: Trace>(board: &Board, trace: &mut T) -> Score {
+    let pawn_entry = pawns::evaluate_pawn_structure(board, trace);
+    evaluate_terms(board, &pawn_entry, trace)
+}
+
+fn evaluate_terms<T: Trace>(board: &Board, pawn_entry: &PawnEntry, trace: &mut T) -> Score {
+    let mut score = evaluate_material(board, Side::White, trace);
+    score -= evaluate_material(board, Side::Black, trace);
+
+    score += pawn_entry.score;
+    score += pawns::evaluate_passed_pawns(board, &pawn_entry.passed, trace);
+
+    let mut info = EvalInfo::new(board);
+    score += evaluate_pieces(board, &mut info, Side::White, trace);
+    score -= evaluate_pieces(board, &mut info, Side::Black, trace);
+    score += evaluate_king_safety(board, &info, Side::White, trace);
+    score -= evaluate_king_safety(board, &info, Side::Black, trace);
+    score += evaluate_threats(board, &info, Side::White, trace);
+    score -= evaluate_threats(board, &info, Side::Black, trace);
+
+    score
+}
+
+/// The endgame scale factor for the side the endgame score favours.
+pub fn scale_factor(board: &Board, score: Score) -> i32 {
+    let strong = if score.eg() >= 0 {
+        Side::White
+    } else {
+        Side::Black
+    };
+    endgame::scale_factor(board, strong)
+}
+
+const fn scale(score: Score, scale: i32) -> Score {
+    Score(score.mg(), score.eg() * scale / endgame::SCALE_NORMAL)
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::{evaluation::*, fen};
+
+    // flip the board vertically and swap the colours of every piece, the
+    // evaluation of the result should be exactly the same for the side to move
+    fn mirror_fen(fen: &str) -> String {
+        let fields: Vec<&str> = fen.split_whitespace().collect();
+        let swap_case = |s: &str| -> String {
+            s.chars()
+                .map(|c| {
+                    if c.is_ascii_uppercase() {
+                        c.to_ascii_lowercase()
+                    } else {
+                        c.to_ascii_uppercase()
+                    }
+                })
+                .collect()
+        };
+
+        let pieces: Vec<&str> = fields[0].split('/').rev().collect();
+        let stm = if fields[1] == "w" { "b" } else { "w" };
+        let ep = match fields[3] {
+            "-" => "-".to_string(),
+            ep => format!("{}{}", &ep[0..1], if &ep[1..] == "3" { 6 } else { 3 }),
+        };
+
+        let mut castling: Vec<char> = swap_case(fields[2]).chars().collect();
+        castling.sort_by_key(|c| "KQkq-".find(*c));
+
+        format!(
+            "{} {} {} {} {} {}",
+            swap_case(&pieces.join("/")),
+            stm,
+            castling.iter().collect::<String>(),
+            ep,
+            fields[4],
+            fields[5]
+        )
+    }
+
+    const SYMMETRY_POSITIONS: [&str; 10] = [
+        "rnbqkbnr/pppppppp/8/8/8/8/PPPPPPPP/RNBQKBNR w KQkq - 0 1",
+        "r3k2r/p1ppqpb1/bn2pnp1/3PN3/1p2P3/2N2Q1p/PPPBBPPP/R3K2R w KQkq - 0 1",
+        "8/2p5/3p4/KP5r/1R3p1k/8/4P1P1/8 w - - 0 1",
+        "4rrk1/pp1n3p/3q2pQ/2p1pb2/2PP4/2P3N1/P2B2PP/4RRK1 b - - 7 19",
+        "6k1/6p1/6Pp/ppp5/3pn2P/1P3K2/1PP2P2/3N4 b - - 0 1",
+        "3b4/5kp1/1p1p1p1p/pP1PpP1P/P1P1P3/3KN3/8/8 w - - 0 1",
+        "8/1p3pp1/7p/5P1P/2k3P1/8/2K2P2/8 w - - 0 1",
+        "r3k2r/3nnpbp/q2pp1p1/p7/Pp1PPPP1/4BNN1/1P5P/R2Q1RK1 w kq - 0 16",
+        "8/8/8/8/1k6/1p6/8/1K5R w - - 0 1",
+        "8/4kb2/8/5p2/4P3/8/3BK3/8 b - - 0 1",
+    ];
+
+    #[test]
+    fn evaluation_is_symmetric() {
+        let mut pawn_table = PawnHashTable::new(1024);
+
+        for fen in SYMMETRY_POSITIONS {
+            let board = fen::fen_to_board(fen).unwrap();
+            let mirrored = fen::fen_to_board(&mirror_fen(fen)).unwrap();
+            assert_eq!(
+                evaluate(&board, &mut pawn_table),
+                evaluate(&mirrored, &mut pawn_table),
+                "{fen}"
+            );
+        }
+    }
+
+    #[test]
+    fn phase_and_material() {
+        let board = fen::fen_to_board(SYMMETRY_POSITIONS[0]).unwrap();
+        assert_eq!(phase(&board), MAX_PHASE);
+        assert_eq!(material(&board), Score(0, 0));
+
+        let board = fen::fen_to_board("4k3/8/8/8/8/8/PPP5/RN2K3 w - - 0 1").unwrap();
+        assert_eq!(phase(&board), 3);
+        assert_eq!(
+            material(&board),
+            PIECE_VALUES[0] * 3 + PIECE_VALUES[1] + PIECE_VALUES[3]
+        );
+    }
+
+    fn board_and_info(fen: &str) -> (Board, EvalInfo) {
+        let board = fen::fen_to_board(fen).unwrap();
+        let mut info = EvalInfo::new(&board);
+        evaluate_pieces(&board, &mut info, Side::White, &mut NoTrace);
+        evaluate_pieces(&board, &mut info, Side::Black, &mut NoTrace);
+        (board, info)
+    }
+
+    #[test]
+    fn piece_terms() {
+        // the rook attacks the king's square but that isn't in its mobility area
+        let board = fen::fen_to_board("4k3/8/8/8/8/8/8/R3K3 w - - 0 1").unwrap();
+        let mut info = EvalInfo::new(&board);
+        assert_eq!(
+            evaluate_pieces(&board, &mut info, Side::White, &mut NoTrace),
+            ROOK_MOBILITY[10] + ROOK_OPEN_FILE
+        );
+
+        let board = fen::fen_to_board("4k3/8/8/3N4/2P5/8/8/4K3 w - - 0 1").unwrap();
+        let mut info = EvalInfo::new(&board);
+        assert_eq!(
+            evaluate_pieces(&board, &mut info, Side::White, &mut NoTrace),
+            KNIGHT_MOBILITY[8] + KNIGHT_OUTPOST
+        );
+
+        let board = fen::fen_to_board("4k3/8/8/8/8/8/8/2B1KB2 w - - 0 1").unwrap();
+        let mut info = EvalInfo::new(&board);
+        assert_eq!(
+            evaluate_pieces(&board, &mut info, Side::White, &mut NoTrace),
+            BISHOP_MOBILITY[7] * 2 + BISHOP_PAIR
+        );
+    }
+
+    #[test]
+    fn king_safety_terms() {
+        let (board, info) = board_and_info("6k1/5ppp/8/8/8/8/5PPP/6K1 w - - 0 1");
+        assert_eq!(
+            evaluate_king_safety(&board, &info, Side::White, &mut NoTrace),
+            PAWN_SHELTER[1] * 3
+        );
+
+        // two attackers on the king zone, the rook on g1 and f1, the knight on g1 and h2
+        let (board, info) = board_and_info("6k1/8/8/8/8/5n2/6P1/4r1K1 w - - 0 1");
+        assert_eq!(
+            evaluate_king_safety(&board, &info, Side::White, &mut NoTrace),
+            PAWN_SHELTER[0] * 2
+                + PAWN_SHELTER[1]
+                + KING_ATTACKER_WEIGHT[PieceType::Knight as usize]
+                + KING_ATTACKER_WEIGHT[PieceType::Rook as usize]
+                + KING_ZONE_ATTACK * 4
+        );
+
+        // the rook can check safely from a1, the knight from e2 but not f3
+        let (board, info) = board_and_info("r5k1/8/8/8/3n4/8/6P1/6K1 w - - 0 1");
+        assert_eq!(
+            evaluate_king_safety(&board, &info, Side::White, &mut NoTrace),
+            PAWN_SHELTER[0] * 2
+                + PAWN_SHELTER[1]
+                + SAFE_CHECK[PieceType::Knight as usize]
+                + SAFE_CHECK[PieceType::Rook as usize]
+        );
+    }
+
+    #[test]
+    fn threat_terms() {
+        let (board, info) = board_and_info("4k3/8/8/3n4/2P5/8/8/4K3 w - - 0 1");
+        assert_eq!(
+            evaluate_threats(&board, &info, Side::White, &mut NoTrace),
+            THREAT_BY_PAWN[PieceType::Knight as usize] + HANGING_PIECE
+        );
+
+        // the rook is defended, but still attacked by a lower valued piece
+        let (board, info) = board_and_info("4k3/4r3/8/3N4/8/8/8/4K3 w - - 0 1");
+        assert_eq!(
+            evaluate_threats(&board, &info, Side::White, &mut NoTrace),
+            THREAT_BY_MINOR[PieceType::Rook as usize]
+        );
+    }
+
+    #[test]
+    fn trace_matches_evaluation() {
+        let mut pawn_table = PawnHashTable::new(1024);
+
+        for fen in SYMMETRY_POSITIONS {
+            let board = fen::fen_to_board(fen).unwrap();
+            let trace = eval_trace(&board).unwrap();
+            let eval = match board.stm {
+                Side::White => trace.eval,
+                Side::Black => -trace.eval,
+            };
+            assert_eq!(eval, evaluate(&board, &mut pawn_table), "{fen}");
+
+            let pawn_entry = pawn_table.probe(&board);
+            assert_eq!(
+                trace.total(),
+                evaluate_terms(&board, &pawn_entry, &mut NoTrace),
+                "{fen}"
+            );
+        }
+    }
+
+    #[test]
+    fn trace_table() {
+        let board = fen::fen_to_board("4k3/pp6/8/8/8/8/PPP5/R3K3 w - - 0 1").unwrap();
+        let expected = "        Term |         White |         Black |         Total
+             |     mg     eg |     mg     eg |     mg     eg
+-------------+---------------+---------------+--------------
+    Material |    723    794 |    164    188 |    559    606
+       Pawns |     12      7 |     10      2 |      2      5
+Passed pawns |      0      0 |      0      0 |      0      0
+    Mobility |      2     20 |      0      0 |      2     20
+      Pieces |      0      0 |      0      0 |      0      0
+ King safety |    -54    -12 |    -54    -12 |      0      0
+     Threats |      0      0 |      0      0 |      0      0
+-------------+---------------+---------------+--------------
+       Total |    683    809 |    120    178 |    563    631
+
+Phase: 2/24
+Endgame scale: 64/64
+Final evaluation: 625 (white side)";
+        assert_eq!(eval_trace(&board).unwrap().to_string(), expected);
+    }
+
+    #[test]
+    fn trace_rejects_missing_kings() {
+        for fen in [
+            "8/8/8/8/8/8/8/8 w - - 0 1",
+            "8/8/8/8/8/8/8/K7 w - - 0 1",
+            "k7/8/8/8/8/8/8/KK6 b - - 0 1",
+        ] {
+            let board = fen::fen_to_board(fen).unwrap();
+            assert!(eval_trace(&board).is_err(), "{fen}");
+        }
+    }
+}
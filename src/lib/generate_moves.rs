@@ -36,7 +36,7 @@ pub fn generate_moves<T: MoveContainer>(
 ) {
     while targets != BB_EMPTY {
         let to = targets.poplsb();
-        let m = Move { from, to, flag };
+        let m = Move::new(from, to, flag);
 
         if in_between(from, to) & pieces == BB_EMPTY
             && (*pinned & SQUARE_BB[from as usize] == BB_EMPTY
@@ -208,6 +208,18 @@ pub fn is_in_check(board: &Board, s: Side) -> bool {
     is_square_threatened(board, board.get_king(s), s)
 }
 
+/// The pieces giving check to the king of side `s`.
+pub fn checkers(board: &Board, s: Side) -> BB {
+    let king = board.get_king(s);
+    let occupied = board.occupied_squares();
+    let enemy = |t: PieceType| board.get_piece_bb(Piece::from_type(t, !s));
+
+    KNIGHT_ATTACKS[king as usize] & enemy(PieceType::Knight)
+        | PAWN_ATTACKS[s as usize][king as usize] & enemy(PieceType::Pawn)
+        | bishop_attacks(king, occupied) & (enemy(PieceType::Bishop) | enemy(PieceType::Queen))
+        | rook_attacks(king, occupied) & (enemy(PieceType::Rook) | enemy(PieceType::Queen))
+}
+
 fn pawn_pushes<T: MoveContainer>(board: &Board, moves: &mut T, pinned: &BB) {
     let forward;
     let mut targets;
@@ -227,11 +239,7 @@ fn pawn_pushes<T: MoveContainer>(board: &Board, moves: &mut T, pinned: &BB) {
         let end = targets.poplsb();
         let start = Square::from_index((end as i8 - forward) as usize);
 
-        let m = Move {
-            from: start,
-            to: end,
-            flag: MoveFlag::Quiet,
-        };
+        let m = Move::new(start, end, MoveFlag::Quiet);
 
         if *pinned & SQUARE_BB[start as usize] == BB_EMPTY || !move_puts_self_in_check(board, &m) {
             moves.push(m);
@@ -258,31 +266,15 @@ fn pawn_promotions<T: MoveContainer>(board: &Board, moves: &mut T, pinned: &BB)
         let end = targets.poplsb();
         let start = Square::from_index((end as i8 - forward) as usize);
 
-        let m = Move {
-            from: start,
-            to: end,
-            flag: MoveFlag::KnightPromotion,
-        };
+        let m = Move::new(start, end, MoveFlag::KnightPromotion);
 
         if *pinned & SQUARE_BB[start as usize] == BB_EMPTY || !move_puts_self_in_check(board, &m) {
             moves.push(m);
-            moves.push(Move {
-                from: start,
-                to: end,
-                flag: MoveFlag::BishopPromotion,
-            });
-
-            moves.push(Move {
-                from: start,
-                to: end,
-                flag: MoveFlag::RookPromotion,
-            });
-
-            moves.push(Move {
-                from: start,
-                to: end,
-                flag: MoveFlag::QueenPromotion,
-            });
+            moves.push(Move::new(start, end, MoveFlag::BishopPromotion));
+
+            moves.push(Move::new(start, end, MoveFlag::RookPromotion));
+
+            moves.push(Move::new(start, end, MoveFlag::QueenPromotion));
         }
     }
 }
@@ -307,11 +299,7 @@ fn pawn_double_pushes<T: MoveContainer>(board: &Board, moves: &mut T, pinned: &B
         let end = targets.poplsb();
         let start = Square::from_index((end as i8 - forward) as usize);
 
-        let m = Move {
-            from: start,
-            to: end,
-            flag: MoveFlag::PawnDoubleMove,
-        };
+        let m = Move::new(start, end, MoveFlag::PawnDoubleMove);
 
         if *pinned & SQUARE_BB[start as usize] == BB_EMPTY || !move_puts_self_in_check(board, &m) {
             moves.push(m);
@@ -331,11 +319,7 @@ fn pawn_en_passant<T: MoveContainer>(board: &Board, moves: &mut T) {
         let start = attackers.poplsb();
         let end = board.en_passant.unwrap();
 
-        let m = Move {
-            from: start,
-            to: end,
-            flag: MoveFlag::EnPassant,
-        };
+        let m = Move::new(start, end, MoveFlag::EnPassant);
 
         if !move_puts_self_in_check(board, &m) {
             moves.push(m);
@@ -370,40 +354,20 @@ fn pawn_captures<T: MoveContainer>(board: &Board, moves: &mut T, pinned: &BB) {
         let end = left_attackers.poplsb();
         let start = Square::from_index((end as i8 - forward_left) as usize);
 
-        let m = Move {
-            from: start,
-            to: end,
-            flag: MoveFlag::Capture,
-        };
+        let m = Move::new(start, end, MoveFlag::Capture);
 
         if *pinned & SQUARE_BB[start as usize] != BB_EMPTY && move_puts_self_in_check(board, &m) {
             continue;
         }
 
         if end.rank() == Rank::Eight || end.rank() == Rank::One {
-            moves.push(Move {
-                from: start,
-                to: end,
-                flag: MoveFlag::KnightPromotionCapture,
-            });
-
-            moves.push(Move {
-                from: start,
-                to: end,
-                flag: MoveFlag::BishopPromotionCapture,
-            });
-
-            moves.push(Move {
-                from: start,
-                to: end,
-                flag: MoveFlag::RookPromotionCapture,
-            });
-
-            moves.push(Move {
-                from: start,
-                to: end,
-                flag: MoveFlag::QueenPromotionCapture,
-            });
+            moves.push(Move::new(start, end, MoveFlag::KnightPromotionCapture));
+
+            moves.push(Move::new(start, end, MoveFlag::BishopPromotionCapture));
+
+            moves.push(Move::new(start, end, MoveFlag::RookPromotionCapture));
+
+            moves.push(Move::new(start, end, MoveFlag::QueenPromotionCapture));
         } else {
             moves.push(m);
         }
@@ -413,40 +377,20 @@ fn pawn_captures<T: MoveContainer>(board: &Board, moves: &mut T, pinned: &BB) {
         let end = right_attackers.poplsb();
         let start = Square::from_index((end as i8 - forward_right) as usize);
 
-        let m = Move {
-            from: start,
-            to: end,
-            flag: MoveFlag::Capture,
-        };
+        let m = Move::new(start, end, MoveFlag::Capture);
 
         if *pinned & SQUARE_BB[start as usize] != BB_EMPTY && move_puts_self_in_check(board, &m) {
             continue;
         }
 
         if end.rank() == Rank::Eight || end.rank() == Rank::One {
-            moves.push(Move {
-                from: start,
-                to: end,
-                flag: MoveFlag::KnightPromotionCapture,
-            });
-
-            moves.push(Move {
-                from: start,
-                to: end,
-                flag: MoveFlag::BishopPromotionCapture,
-            });
-
-            moves.push(Move {
-                from: start,
-                to: end,
-                flag: MoveFlag::RookPromotionCapture,
-            });
-
-            moves.push(Move {
-                from: start,
-                to: end,
-                flag: MoveFlag::QueenPromotionCapture,
-            });
+            moves.push(Move::new(start, end, MoveFlag::KnightPromotionCapture));
+
+            moves.push(Move::new(start, end, MoveFlag::BishopPromotionCapture));
+
+            moves.push(Move::new(start, end, MoveFlag::RookPromotionCapture));
+
+            moves.push(Move::new(start, end, MoveFlag::QueenPromotionCapture));
         } else {
             moves.push(m);
         }
@@ -463,11 +407,7 @@ fn castle_moves<T: MoveContainer>(board: &Board, moves: &mut T) {
             && !is_square_threatened(board, path_2, board.stm)
             && !is_square_threatened(board, path_3, board.stm)
         {
-            moves.push(Move {
-                from: path_1,
-                to: path_3,
-                flag: move_flag,
-            });
+            moves.push(Move::new(path_1, path_3, move_flag));
         }
     };
 
@@ -515,7 +455,8 @@ fn move_puts_self_in_check(board: &Board, m: &Move) -> bool {
     is_in_check(&board, !board.stm) // the STM has changed after we made the move
 }
 
-fn is_square_threatened(board: &Board, square: Square, side: Side) -> bool {
+/// Whether a piece of side `side` on `square` would be attacked.
+pub fn is_square_threatened(board: &Board, square: Square, side: Side) -> bool {
     if KNIGHT_ATTACKS[square as usize]
         & board.get_piece_bb(Piece::from_type(PieceType::Knight, !side))
         != BB_EMPTY
@@ -0,0 +1,410 @@
+use crate::board::Board;
+use crate::definitions::*;
+
+use std::sync::OnceLock;
+use strum::IntoEnumIterator;
+
+// The pawn is always white and on files a to d, any other position is
+// mirrored into this form. There are 24 pawn squares, 64 squares for each king
+// and two sides to move.
+const MAX_INDEX: usize = 2 * 24 * 64 * 64;
+
+/// Positions with a white king, white pawn and black king, stored as one bit
+/// per position, set if white wins.
+struct Bitbase {
+    bits: Vec<u32>,
+}
+
+impl Bitbase {
+    fn is_win(&self, index: usize) -> bool {
+        self.bits[index / 32] & (1 << (index % 32)) != 0
+    }
+}
+
+fn index(stm: Side, white_king: Square, black_king: Square, pawn: Square) -> usize {
+    white_king as usize
+        | (black_king as usize) << 6
+        | (stm as usize) << 12
+        | (pawn.file() as usize) << 13
+        | (Rank::Seven as usize - pawn.rank() as usize) << 15
+}
+
+// Retrograde analysis works with these flags, a position is a win if white can
+// reach a win, or black can't avoid one
+const INVALID: u8 = 0;
+const UNKNOWN: u8 = 1;
+const DRAW: u8 = 2;
+const WIN: u8 = 4;
+
+#[derive(Copy, Clone)]
+struct Position {
+    stm: Side,
+    white_king: Square,
+    black_king: Square,
+    pawn: Square,
+    result: u8,
+}
+
+impl Position {
+    fn from_index(idx: usize) -> Position {
+        let white_king = Square::from_index(idx & 0x3f);
+        let black_king = Square::from_index((idx >> 6) & 0x3f);
+        let stm = if (idx >> 12) & 1 == 0 {
+            Side::White
+        } else {
+            Side::Black
+        };
+        let pawn = Square::from_coord(
+            File::from_index((idx >> 13) & 0x3),
+            Rank::from_index(Rank::Seven as usize - (idx >> 15)),
+        );
+
+        let mut pos = Position {
+            stm,
+            white_king,
+            black_king,
+            pawn,
+            result: UNKNOWN,
+        };
+        pos.result = pos.initial_result();
+        pos
+    }
+
+    // Positions that can be classified without looking at the moves
+    fn initial_result(&self) -> u8 {
+        let push = Square::from_index(self.pawn as usize + 8);
+        let white_attacks = KING_ATTACKS[self.white_king as usize];
+        let black_attacks = KING_ATTACKS[self.black_king as usize];
+
+        if self.white_king.distance(self.black_king) <= 1
+            || self.white_king == self.pawn
+            || self.black_king == self.pawn
+            || (self.stm == Side::White
+                && PAWN_ATTACKS[Side::White as usize][self.pawn as usize] & self.black_king.to_bb()
+                    != BB_EMPTY)
+        {
+            return INVALID;
+        }
+
+        // the pawn promotes and the new queen can't be captured
+        if self.stm == Side::White
+            && self.pawn.rank() == Rank::Seven
+            && self.white_king != push
+            && (self.black_king.distance(push) > 1 || self.white_king.distance(push) == 1)
+        {
+            return WIN;
+        }
+
+        // black is stalemated, or captures an undefended pawn
+        if self.stm == Side::Black
+            && (black_attacks
+                & !(white_attacks | PAWN_ATTACKS[Side::White as usize][self.pawn as usize])
+                == BB_EMPTY
+                || black_attacks & !white_attacks & self.pawn.to_bb() != BB_EMPTY)
+        {
+            return DRAW;
+        }
+
+        UNKNOWN
+    }
+
+    // Combine the results of the positions reachable from here. White wins if
+    // any move wins, black draws if any move draws.
+    fn classify(&self, db: &[Position]) -> u8 {
+        let (good, bad) = match self.stm {
+            Side::White => (WIN, DRAW),
+            Side::Black => (DRAW, WIN),
+        };
+
+        let mut r = INVALID;
+        let mut moves = match self.stm {
+            Side::White => KING_ATTACKS[self.white_king as usize],
+            Side::Black => KING_ATTACKS[self.black_king as usize],
+        };
+        while moves != BB_EMPTY {
+            let to = moves.poplsb();
+            r |= match self.stm {
+                Side::White => db[index(Side::Black, to, self.black_king, self.pawn)].result,
+                Side::Black => db[index(Side::White, self.white_king, to, self.pawn)].result,
+            };
+        }
+
+        if self.stm == Side::White && self.pawn.rank() != Rank::Seven {
+            let push = Square::from_index(self.pawn as usize + 8);
+            r |= db[index(Side::Black, self.white_king, self.black_king, push)].result;
+
+            let double_push = Square::from_index(push as usize + 8);
+            if self.pawn.rank() == Rank::Two && push != self.white_king && push != self.black_king {
+                r |= db[index(Side::Black, self.white_king, self.black_king, double_push)].result;
+            }
+        }
+
+        if r & good != 0 {
+            good
+        } else if r & UNKNOWN != 0 {
+            UNKNOWN
+        } else {
+            bad
+        }
+    }
+}
+
+fn generate() -> Bitbase {
+    let mut db: Vec<Position> = (0..MAX_INDEX).map(Position::from_index).collect();
+
+    // keep resolving unknown positions until nothing changes, whatever is
+    // left over can't be won
+    let mut changed = true;
+    while changed {
+        changed = false;
+        for idx in 0..MAX_INDEX {
+            if db[idx].result == UNKNOWN {
+                let result = db[idx].classify(&db);
+                if result != UNKNOWN {
+                    db[idx].result = result;
+                    changed = true;
+                }
+            }
+        }
+    }
+
+    let mut bits = vec![0; MAX_INDEX / 32];
+    for (idx, pos) in db.iter().enumerate() {
+        if pos.result == WIN {
+            bits[idx / 32] |= 1 << (idx % 32);
+        }
+    }
+    Bitbase { bits }
+}
+
+fn bitbase() -> &'static Bitbase {
+    static BITBASE: OnceLock<Bitbase> = OnceLock::new();
+    BITBASE.get_or_init(generate)
+}
+
+/// Probe the KPK bitbase. Returns `None` unless the board has only the two
+/// kings and a single pawn, or if the pawn is on the first or last rank where
+/// no legal position has one. Otherwise whether the side with the pawn wins
+/// with best play.
+///
+/// The bitbase is generated by retrograde analysis the first time it is
+/// probed, which takes a fraction of a second. It is 24KB.
+pub fn probe(board: &Board) -> Option<bool> {
+    let mut pawn = None;
+    for piece in Piece::iter() {
+        let bb = board.get_piece_bb(piece);
+        match piece.get_type() {
+            PieceType::King => {}
+            PieceType::Pawn if bb.popcnt() == 1 && pawn.is_none() => {
+                pawn = Some((bb.ctz(), piece.get_side()))
+            }
+            _ if bb == BB_EMPTY => {}
+            _ => return None,
+        }
+    }
+    let (pawn, strong) = pawn?;
+    if matches!(pawn.rank(), Rank::One | Rank::Eight) {
+        return None;
+    }
+
+    // flip the board so the strong side is white, and mirror so the pawn is on
+    // files a to d
+    let normalise = |sq: Square| {
+        let sq = match strong {
+            Side::White => sq as usize,
+            Side::Black => sq as usize ^ 56,
+        };
+        let sq = if pawn.file() as usize >= File::E as usize {
+            sq ^ 7
+        } else {
+            sq
+        };
+        Square::from_index(sq)
+    };
+
+    let stm = if board.stm == strong {
+        Side::White
+    } else {
+        Side::Black
+    };
+
+    Some(bitbase().is_win(index(
+        stm,
+        normalise(board.get_king(strong)),
+        normalise(board.get_king(!strong)),
+        normalise(pawn),
+    )))
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::chess_move::Move;
+    use crate::generate_moves::legal_moves;
+    use crate::{fen, kpk::*};
+    use std::collections::HashMap;
+    use strum::EnumCount;
+
+    // A brute force search on the real board, where the strong side wins by
+    // promoting to a queen that can't be taken straight away
+    type Key = (usize, usize, usize, bool);
+
+    struct BruteForce {
+        strong: Side,
+        // a win within some depth is also a win at any greater depth, and a
+        // position not won within some depth isn't won at any lesser depth
+        won_within: HashMap<Key, u32>,
+        not_won_within: HashMap<Key, u32>,
+    }
+
+    impl BruteForce {
+        fn wins(&mut self, board: &Board, depth: u32) -> bool {
+            let pawn_bb = board.get_piece_bb(Piece::from_type(PieceType::Pawn, self.strong));
+            if pawn_bb == BB_EMPTY {
+                let queen = board.get_piece_bb(Piece::from_type(PieceType::Queen, self.strong));
+                if queen == BB_EMPTY {
+                    return false;
+                }
+                // the weak side is to move, it must not be able to take the queen
+                let mut moves = Vec::new();
+                legal_moves(board, &mut moves);
+                return !moves.is_empty() && moves.iter().all(|m| m.to() != queen.ctz());
+            }
+            if depth == 0 {
+                return false;
+            }
+
+            let key = (
+                board.get_king(Side::White) as usize,
+                board.get_king(Side::Black) as usize,
+                pawn_bb.ctz() as usize,
+                board.stm == self.strong,
+            );
+            if self.won_within.get(&key).is_some_and(|&d| d <= depth) {
+                return true;
+            }
+            if self.not_won_within.get(&key).is_some_and(|&d| d >= depth) {
+                return false;
+            }
+
+            let mut moves: Vec<Move> = Vec::new();
+            legal_moves(board, &mut moves);
+            let attacking = board.stm == self.strong;
+
+            let mut child = |m: &Move| {
+                let mut child = *board;
+                child.make_move(m);
+                self.wins(&child, depth - 1)
+            };
+            let result = if attacking {
+                moves
+                    .iter()
+                    .filter(|m| !m.is_promotion() || m.flag() as u8 & 0b11 == 0b11)
+                    .any(&mut child)
+            } else {
+                !moves.is_empty() && moves.iter().all(&mut child)
+            };
+
+            if result {
+                self.won_within.insert(key, depth);
+            } else {
+                self.not_won_within.insert(key, depth);
+            }
+            result
+        }
+    }
+
+    fn brute_force(board: &Board, depth: u32) -> bool {
+        let strong = if board.get_piece_bb(Piece::WhitePawn) != BB_EMPTY {
+            Side::White
+        } else {
+            Side::Black
+        };
+        BruteForce {
+            strong,
+            won_within: HashMap::new(),
+            not_won_within: HashMap::new(),
+        }
+        .wins(board, depth)
+    }
+
+    // Every position here is decided within the brute force horizon, so a
+    // position it can't win is really a draw
+    #[test]
+    fn matches_brute_force() {
+        let positions = [
+            // the king on the sixth rank in front of the pawn wins either way
+            ("4k3/8/4K3/4P3/8/8/8/8 w - - 0 1", true),
+            ("4k3/8/4K3/4P3/8/8/8/8 b - - 0 1", true),
+            // further back, the opposition decides
+            ("8/8/3k4/8/3K4/3P4/8/8 w - - 0 1", false),
+            ("8/8/3k4/8/3K4/3P4/8/8 b - - 0 1", true),
+            // the king holds a key square
+            ("8/4k3/8/3K4/3P4/8/8/8 w - - 0 1", true),
+            // the rook pawn is a draw once the king reaches the corner
+            ("k7/8/8/8/P7/8/8/4K3 w - - 0 1", false),
+            ("7k/8/6K1/7P/8/8/8/8 w - - 0 1", false),
+            // the pawn outruns the king
+            ("8/8/8/5k2/P7/8/8/7K w - - 0 1", true),
+            ("8/8/8/5k2/P7/8/8/7K b - - 0 1", false),
+            // the pawn is taken
+            ("8/8/8/8/8/8/kP6/7K b - - 0 1", false),
+            // the colours reversed
+            ("8/8/8/3p4/3k4/8/3K4/8 w - - 0 1", true),
+            ("8/8/8/3p4/3k4/8/3K4/8 b - - 0 1", false),
+        ];
+
+        for (fen, wins) in positions {
+            let board = fen::fen_to_board(fen).unwrap_or_else(|_| panic!("{fen}"));
+            assert_eq!(probe(&board), Some(wins), "{fen}");
+            assert_eq!(brute_force(&board, 21), wins, "{fen}");
+        }
+    }
+
+    #[test]
+    fn mirrored_positions_agree() {
+        for fen in [
+            "8/8/8/8/P7/8/8/5K1k w - - 0 1",
+            "8/4k3/8/3K4/3P4/8/8/8 w - - 0 1",
+            "8/8/3k4/8/3K4/3P4/8/8 b - - 0 1",
+        ] {
+            let board = fen::fen_to_board(fen).unwrap();
+
+            let mut mirrored = Board::new();
+            for sq in (0..Square::COUNT).map(Square::from_index) {
+                if let Some(piece) = board.get_square(sq) {
+                    mirrored.set_square(Square::from_index(sq as usize ^ 7), piece);
+                }
+            }
+            mirrored.stm = board.stm;
+            assert_eq!(probe(&mirrored), probe(&board), "{fen}");
+        }
+    }
+
+    #[test]
+    fn not_kpk() {
+        let board = fen::fen_to_board("8/8/8/8/P7/8/1P6/5K1k w - - 0 1").unwrap();
+        assert_eq!(probe(&board), None);
+        let board = fen::fen_to_board("8/8/8/8/P7/8/1N6/5K1k w - - 0 1").unwrap();
+        assert_eq!(probe(&board), None);
+    }
+
+    #[test]
+    fn pawn_on_back_rank() {
+        // the FEN parser accepts pawns on the first and last ranks, but they
+        // have no place in the bitbase
+        for fen in [
+            "3P4/8/8/8/8/8/8/5K1k w - - 0 1",
+            "8/8/8/8/8/8/8/P4K1k b - - 0 1",
+            "p7/8/8/8/8/8/8/5K1k w - - 0 1",
+            "8/8/8/8/8/8/8/3p1K1k b - - 0 1",
+        ] {
+            let board = fen::fen_to_board(fen).unwrap();
+            assert_eq!(probe(&board), None, "{fen}");
+            assert_eq!(
+                crate::endgame::evaluate_endgame(&board, crate::endgame::material_key(&board)),
+                Some(0),
+                "{fen}"
+            );
+        }
+    }
+}
@@ -1,6 +1,21 @@
+pub mod bench;
 pub mod board;
 pub mod chess_move;
+pub mod correction_history;
 pub mod definitions;
+pub mod endgame;
+pub mod evaluation;
 pub mod fen;
 pub mod generate_moves;
+pub mod kpk;
+pub mod mate_search;
+pub mod nnue;
+pub mod nnue_trainer;
+pub mod pawns;
 pub mod perft;
+pub mod proof_number_search;
+pub mod time_manager;
+pub mod trace;
+pub mod training_data;
+pub mod tuner;
+pub mod zobrist;
@@ -0,0 +1,265 @@
+use crate::board::Board;
+use crate::chess_move::Move;
+use crate::definitions::*;
+use crate::generate_moves::{is_in_check, legal_moves};
+
+/// The score for delivering checkmate on the current move. Mate scores are
+/// expressed as `MATE - ply`, so shorter mates score higher.
+pub const MATE: i32 = 32000;
+
+/// Scores within this many plies of `MATE` are mate scores.
+pub const MAX_PLY: i32 = 256;
+
+pub const fn mate_in(ply: u32) -> i32 {
+    MATE - ply as i32
+}
+
+pub const fn mated_in(ply: u32) -> i32 {
+    -MATE + ply as i32
+}
+
+pub const fn is_mate_score(score: i32) -> bool {
+    score.abs() >= MATE - MAX_PLY
+}
+
+/// Mate scores in the search are relative to the root, but a position stored in
+/// a transposition table may be reached at a different ply. Before storing, we
+/// convert the score to be relative to the position itself.
+///
+/// # Examples
+/// ```
+/// # use oxide::mate_search::*;
+/// // mate in 5 plies from the root, found at ply 2, is a mate in 3 from here
+/// assert_eq!(score_to_tt(mate_in(5), 2), mate_in(3));
+/// assert_eq!(score_from_tt(mate_in(3), 2), mate_in(5));
+/// ```
+pub const fn score_to_tt(score: i32, ply: u32) -> i32 {
+    if score >= MATE - MAX_PLY {
+        score + ply as i32
+    } else if score <= -MATE + MAX_PLY {
+        score - ply as i32
+    } else {
+        score
+    }
+}
+
+/// The inverse of `score_to_tt`, converting a stored score back to be relative
+/// to the root.
+pub const fn score_from_tt(score: i32, ply: u32) -> i32 {
+    if score >= MATE - MAX_PLY {
+        score - ply as i32
+    } else if score <= -MATE + MAX_PLY {
+        score + ply as i32
+    } else {
+        score
+    }
+}
+
+#[derive(Debug, Clone)]
+pub enum MateResult {
+    /// The side to move mates in `moves` moves, the principal variation is the
+    /// attacking line with the best defence.
+    Mate { moves: u32, pv: Vec<Move> },
+    /// There is no forced mate within the requested number of moves.
+    NoMate,
+}
+
+/// Prove or refute that the side to move can force checkmate within `max_moves`
+/// moves, as in `go mate N`. The shortest mate found is returned.
+///
+/// This is a full width alpha-beta search where every non-mate leaf scores as a
+/// draw, so it is exact. The only pruning is mate distance pruning, and on the
+/// attacker's final move only checking moves are searched because nothing else
+/// can deliver mate. Draws by repetition and the fifty move rule are ignored.
+pub fn mate_search(board: &Board, max_moves: u32) -> MateResult {
+    let search = MateSearch {
+        attacker: board.stm,
+    };
+
+    for moves in 1..=max_moves {
+        let mut pv = Vec::new();
+        let score = search.search(board, moves * 2 - 1, 0, -MATE, MATE, &mut pv);
+        if score > 0 {
+            return MateResult::Mate {
+                moves: (MATE - score + 1) as u32 / 2,
+                pv,
+            };
+        }
+    }
+
+    MateResult::NoMate
+}
+
+struct MateSearch {
+    attacker: Side,
+}
+
+impl MateSearch {
+    fn search(
+        &self,
+        board: &Board,
+        depth: u32,
+        ply: u32,
+        mut alpha: i32,
+        mut beta: i32,
+        pv: &mut Vec<Move>,
+    ) -> i32 {
+        pv.clear();
+
+        // mate distance pruning: even mating on the next move can't beat a
+        // shorter mate already found, and being mated here can't be worse than
+        // a quicker mate
+        alpha = alpha.max(mated_in(ply));
+        beta = beta.min(mate_in(ply + 1));
+        if alpha >= beta {
+            return alpha;
+        }
+
+        let mut moves = arrayvec::ArrayVec::<Move, 256>::new();
+        legal_moves(board, &mut moves);
+
+        if moves.is_empty() {
+            return if is_in_check(board, board.stm) {
+                mated_in(ply)
+            } else {
+                0
+            };
+        }
+
+        if depth == 0 {
+            return 0;
+        }
+
+        let attacking = board.stm == self.attacker;
+
+        // on the attacker's last move only a check can be mate, and the
+        // attacker generally wants to try checks first anyway
+        let mut children = arrayvec::ArrayVec::<(Move, Board, bool), 256>::new();
+        for m in moves {
+            let mut child = *board;
+            child.make_move(&m);
+            let gives_check = attacking && is_in_check(&child, child.stm);
+            if depth == 1 && attacking && !gives_check {
+                continue;
+            }
+            children.push((m, child, gives_check));
+        }
+
+        if children.is_empty() {
+            return 0;
+        }
+
+        if attacking {
+            children.sort_by_key(|(m, _, gives_check)| (!gives_check, !m.is_capture()));
+        }
+
+        let mut best = -MATE;
+        let mut child_pv = Vec::new();
+
+        for (m, child, _) in children {
+            let score = -self.search(&child, depth - 1, ply + 1, -beta, -alpha, &mut child_pv);
+
+            if score > best {
+                best = score;
+            }
+
+            if score > alpha {
+                alpha = score;
+                pv.clear();
+                pv.push(m);
+                pv.extend_from_slice(&child_pv);
+            }
+
+            if alpha >= beta {
+                break;
+            }
+        }
+
+        best
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::{fen, mate_search::*};
+
+    fn assert_mate(fen: &str, moves: u32) {
+        let board = fen::fen_to_board(fen).unwrap();
+
+        match mate_search(&board, moves) {
+            MateResult::Mate { moves: found, pv } => {
+                assert_eq!(found, moves, "{fen}");
+                assert_eq!(pv.len() as u32, moves * 2 - 1, "{fen}");
+
+                // the pv must end in checkmate
+                let mut board = board;
+                for m in &pv {
+                    board.make_move(m);
+                }
+                let mut replies = Vec::new();
+                legal_moves(&board, &mut replies);
+                assert!(replies.is_empty() && is_in_check(&board, board.stm));
+            }
+            MateResult::NoMate => panic!("No mate found in {fen}"),
+        }
+
+        if moves > 1 {
+            assert!(matches!(mate_search(&board, moves - 1), MateResult::NoMate));
+        }
+    }
+
+    #[test]
+    fn mate_in_one() {
+        assert_mate("6k1/5ppp/8/8/8/8/8/R5K1 w - - 0 1", 1);
+        assert_mate("k7/8/1K6/8/8/8/8/7R w - - 0 1", 1);
+    }
+
+    #[test]
+    fn mate_in_two() {
+        assert_mate(
+            "r2qkb1r/pp2nppp/3p4/2pNN1B1/2BnP3/3P4/PPP2PPP/R2bK2R w KQkq - 1 1",
+            2,
+        );
+        assert_mate("kbK5/pp6/1P6/8/8/8/8/R7 w - - 0 1", 2);
+        assert_mate("5k2/8/4K3/8/8/8/8/7R w - - 0 1", 2);
+    }
+
+    #[test]
+    fn mate_in_three() {
+        assert_mate(
+            "r1b1kb1r/pppp1ppp/5q2/4n3/3KP3/2N3PN/PPP4P/R1BQ1B1R b kq - 0 1",
+            3,
+        );
+        assert_mate("8/8/8/8/8/8/R7/5K1k w - - 0 1", 3);
+    }
+
+    #[test]
+    fn mate_in_four() {
+        // Philidor's legacy, ending in a smothered mate
+        assert_mate("r6k/6pp/8/6N1/8/1Q6/8/6K1 w - - 0 1", 4);
+    }
+
+    #[test]
+    fn no_mate() {
+        let board = fen::fen_to_board("8/8/8/3k4/8/3K4/8/7R w - - 0 1").unwrap();
+        assert!(matches!(mate_search(&board, 3), MateResult::NoMate));
+
+        // stalemate is not a win
+        let board = fen::fen_to_board("k7/8/1Q6/8/8/8/8/7K b - - 0 1").unwrap();
+        assert!(matches!(mate_search(&board, 2), MateResult::NoMate));
+    }
+
+    #[test]
+    fn mate_scores() {
+        assert!(is_mate_score(mate_in(10)));
+        assert!(is_mate_score(mated_in(10)));
+        assert!(!is_mate_score(1000));
+
+        for ply in 0..20 {
+            for score in [mate_in(30), mated_in(30), 150, -150, 0] {
+                assert_eq!(score_from_tt(score_to_tt(score, ply), ply), score);
+            }
+        }
+        assert_eq!(score_to_tt(mated_in(7), 3), mated_in(4));
+    }
+}
@@ -0,0 +1,579 @@
+use crate::board::{Board, MoveDelta};
+use crate::definitions::*;
+
+use strum::{EnumCount, IntoEnumIterator};
+
+/// One input for each piece on each square, from the point of view of the side
+/// the accumulator belongs to.
+pub const INPUTS: usize = Piece::COUNT * Square::COUNT;
+
+/// The size of the hidden layer, each side has its own accumulator of this
+/// size and both are fed to the output layer.
+pub const HIDDEN: usize = 256;
+
+/// Quantisation of the feature transformer and the output layer.
+pub const QA: i32 = 255;
+pub const QB: i32 = 64;
+
+/// The network output is scaled by this to convert it to centipawns.
+pub const SCALE: i32 = 400;
+
+const MAGIC: [u8; 4] = *b"OXNN";
+const VERSION: u32 = 1;
+const HEADER_SIZE: usize = 12;
+
+/// The size of a network file in bytes, the header followed by the feature
+/// weights, feature biases, output weights and output bias as little endian
+/// 16 bit integers.
+pub const NETWORK_SIZE: usize = HEADER_SIZE + 2 * (INPUTS * HIDDEN + HIDDEN + 2 * HIDDEN + 1);
+
+/// The network embedded in the binary by the build script. This is empty if
+/// the engine was built without one.
+pub static DEFAULT_NETWORK: &[u8] = include_bytes!(concat!(env!("OUT_DIR"), "/default.nnue"));
+
+/// The value of the `EvalFile` option that selects the embedded network.
+pub const DEFAULT_EVAL_FILE: &str = "<default>";
+
+/// The activation applied to the accumulators before the output layer.
+#[derive(Copy, Clone, Debug, PartialEq)]
+pub enum Activation {
+    /// Clipped ReLU, `clamp(x, 0, QA)`.
+    CReLU,
+    /// Squared clipped ReLU, `clamp(x, 0, QA)^2`.
+    SCReLU,
+}
+
+impl Activation {
+    /// A hash of the network architecture stored in the header, so that a net
+    /// trained for a different architecture can't be loaded.
+    pub const fn architecture_hash(&self) -> u32 {
+        let mut hash: u32 = 0x811c_9dc5;
+        let fields = [
+            INPUTS as u32,
+            HIDDEN as u32,
+            QA as u32,
+            QB as u32,
+            *self as u32,
+        ];
+        let mut i = 0;
+        while i < fields.len() {
+            hash = (hash ^ fields[i]).wrapping_mul(0x0100_0193);
+            i += 1;
+        }
+        hash
+    }
+
+    fn from_architecture_hash(hash: u32) -> Option<Activation> {
+        [Activation::CReLU, Activation::SCReLU]
+            .into_iter()
+            .find(|a| a.architecture_hash() == hash)
+    }
+}
+
+#[derive(Debug)]
+pub enum NnueError {
+    Io(std::io::Error),
+    /// The file doesn't start with the network magic bytes.
+    InvalidHeader,
+    UnsupportedVersion(u32),
+    /// The architecture hash doesn't match any architecture we can run.
+    ArchitectureMismatch(u32),
+    InvalidSize {
+        expected: usize,
+        found: usize,
+    },
+}
+
+impl std::fmt::Display for NnueError {
+    fn fmt(&self, f: &mut std::fmt::Formatter) -> std::fmt::Result {
+        match self {
+            NnueError::Io(e) => write!(f, "Could not read network: {e}"),
+            NnueError::InvalidHeader => write!(f, "Not a network file"),
+            NnueError::UnsupportedVersion(v) => write!(f, "Unsupported network version {v}"),
+            NnueError::ArchitectureMismatch(hash) => {
+                write!(f, "Unsupported network architecture {hash:#010x}")
+            }
+            NnueError::InvalidSize { expected, found } => {
+                write!(f, "Network is {found} bytes, expected {expected}")
+            }
+        }
+    }
+}
+
+impl std::error::Error for NnueError {}
+
+impl From<std::io::Error> for NnueError {
+    fn from(e: std::io::Error) -> Self {
+        NnueError::Io(e)
+    }
+}
+
+/// A (768->N)x2->1 network. The feature transformer is shared between both
+/// sides, and the output layer sees the side to move's accumulator first.
+#[derive(Clone, PartialEq)]
+pub struct Network {
+    pub activation: Activation,
+    /// Indexed by input then hidden neuron.
+    pub feature_weights: Vec<i16>,
+    pub feature_bias: Vec<i16>,
+    /// The weights for the side to move followed by the other side.
+    pub output_weights: Vec<i16>,
+    pub output_bias: i16,
+}
+
+impl Network {
+    pub fn load(path: &std::path::Path) -> Result<Network, NnueError> {
+        Network::from_bytes(&std::fs::read(path)?)
+    }
+
+    /// The embedded network, if there is one.
+    pub fn default_network() -> Option<Result<Network, NnueError>> {
+        Network::from_embedded(DEFAULT_NETWORK)
+    }
+
+    // An embedded network, which is empty if the engine was built without one
+    fn from_embedded(bytes: &[u8]) -> Option<Result<Network, NnueError>> {
+        if bytes.is_empty() {
+            None
+        } else {
+            Some(Network::from_bytes(bytes))
+        }
+    }
+
+    /// The network selected by the `EvalFile` option, either a path or
+    /// `DEFAULT_EVAL_FILE` for the embedded network. `None` if the embedded
+    /// network is selected and there isn't one.
+    pub fn from_eval_file(eval_file: &str) -> Option<Result<Network, NnueError>> {
+        if eval_file.is_empty() || eval_file == DEFAULT_EVAL_FILE {
+            Network::default_network()
+        } else {
+            Some(Network::load(std::path::Path::new(eval_file)))
+        }
+    }
+
+    pub fn from_bytes(bytes: &[u8]) -> Result<Network, NnueError> {
+        if bytes.len() < HEADER_SIZE || bytes[0..4] != MAGIC {
+            return Err(NnueError::InvalidHeader);
+        }
+
+        let read_u32 = |i: usize| u32::from_le_bytes(bytes[i..i + 4].try_into().unwrap());
+        let version = read_u32(4);
+        if version != VERSION {
+            return Err(NnueError::UnsupportedVersion(version));
+        }
+
+        let hash = read_u32(8);
+        let activation = Activation::from_architecture_hash(hash)
+            .ok_or(NnueError::ArchitectureMismatch(hash))?;
+
+        if bytes.len() != NETWORK_SIZE {
+            return Err(NnueError::InvalidSize {
+                expected: NETWORK_SIZE,
+                found: bytes.len(),
+            });
+        }
+
+        let mut values = bytes[HEADER_SIZE..]
+            .chunks_exact(2)
+            .map(|c| i16::from_le_bytes([c[0], c[1]]));
+        let mut take = |n: usize| -> Vec<i16> { values.by_ref().take(n).collect() };
+
+        Ok(Network {
+            activation,
+            feature_weights: take(INPUTS * HIDDEN),
+            feature_bias: take(HIDDEN),
+            output_weights: take(2 * HIDDEN),
+            output_bias: take(1)[0],
+        })
+    }
+
+    /// Serialise the network in the format read by `from_bytes`.
+    pub fn to_bytes(&self) -> Vec<u8> {
+        let mut bytes = Vec::with_capacity(NETWORK_SIZE);
+        bytes.extend_from_slice(&MAGIC);
+        bytes.extend_from_slice(&VERSION.to_le_bytes());
+        bytes.extend_from_slice(&self.activation.architecture_hash().to_le_bytes());
+
+        let values = self
+            .feature_weights
+            .iter()
+            .chain(&self.feature_bias)
+            .chain(&self.output_weights)
+            .chain(std::iter::once(&self.output_bias));
+        for v in values {
+            bytes.extend_from_slice(&v.to_le_bytes());
+        }
+        bytes
+    }
+
+    /// Evaluate the board from scratch, in centipawns from the point of view of
+    /// the side to move.
+    pub fn evaluate_board(&self, board: &Board) -> i32 {
+        self.evaluate(&Accumulator::new(self, board), board.stm)
+    }
+
+    /// Evaluate the position the accumulator was built for, in centipawns from
+    /// the point of view of the side to move.
+    pub fn evaluate(&self, accumulator: &Accumulator, stm: Side) -> i32 {
+        let us = &accumulator.values[stm as usize].0;
+        let them = &accumulator.values[!stm as usize].0;
+        let (our_weights, their_weights) = self.output_weights.split_at(HIDDEN);
+
+        let sum = output_sum(self.activation, us, our_weights).wrapping_add(output_sum(
+            self.activation,
+            them,
+            their_weights,
+        ));
+
+        match self.activation {
+            // the squared activation carries an extra factor of QA
+            Activation::CReLU => (sum + self.output_bias as i32) * SCALE / (QA * QB),
+            Activation::SCReLU => (sum / QA + self.output_bias as i32) * SCALE / (QA * QB),
+        }
+    }
+}
+
+/// The index of the input for a piece on a square, from the point of view of
+/// one side. The board is flipped for black, so that both sides see their own
+/// pieces first and moving up the board.
+pub const fn feature_index(perspective: Side, piece: Piece, square: Square) -> usize {
+    let (side, square) = match perspective {
+        Side::White => (piece.get_side() as usize, square as usize),
+        Side::Black => (1 - piece.get_side() as usize, square as usize ^ 56),
+    };
+    side * PieceType::COUNT * Square::COUNT + piece.get_type() as usize * Square::COUNT + square
+}
+
+#[derive(Clone, PartialEq)]
+#[repr(align(64))]
+struct Align64([i16; HIDDEN]);
+
+/// The hidden layer for each side, indexed by side. This is kept up to date as
+/// moves are made, rather than recomputed from the board for every evaluation.
+#[derive(Clone, PartialEq)]
+pub struct Accumulator {
+    values: [Align64; Side::COUNT],
+}
+
+impl Accumulator {
+    /// Build the accumulators from scratch.
+    pub fn new(network: &Network, board: &Board) -> Accumulator {
+        let mut accumulator = Accumulator {
+            values: [
+                Align64(network.feature_bias[..].try_into().unwrap()),
+                Align64(network.feature_bias[..].try_into().unwrap()),
+            ],
+        };
+
+        for piece in Piece::iter() {
+            let mut bb = board.get_piece_bb(piece);
+            while bb != BB_EMPTY {
+                accumulator.add(network, piece, bb.poplsb());
+            }
+        }
+        accumulator
+    }
+
+    /// Update the accumulators with the pieces moved by
+    /// `Board::make_move_with_delta`.
+    pub fn update(&mut self, network: &Network, delta: &MoveDelta) {
+        for &(piece, square) in &delta.removed {
+            self.remove(network, piece, square);
+        }
+        for &(piece, square) in &delta.added {
+            self.add(network, piece, square);
+        }
+    }
+
+    pub fn add(&mut self, network: &Network, piece: Piece, square: Square) {
+        for side in [Side::White, Side::Black] {
+            let weights = feature_weights(network, feature_index(side, piece, square));
+            for (v, w) in self.values[side as usize].0.iter_mut().zip(weights) {
+                *v = v.wrapping_add(*w);
+            }
+        }
+    }
+
+    pub fn remove(&mut self, network: &Network, piece: Piece, square: Square) {
+        for side in [Side::White, Side::Black] {
+            let weights = feature_weights(network, feature_index(side, piece, square));
+            for (v, w) in self.values[side as usize].0.iter_mut().zip(weights) {
+                *v = v.wrapping_sub(*w);
+            }
+        }
+    }
+}
+
+fn feature_weights(network: &Network, feature: usize) -> &[i16] {
+    &network.feature_weights[feature * HIDDEN..(feature + 1) * HIDDEN]
+}
+
+fn output_sum(activation: Activation, accumulator: &[i16; HIDDEN], weights: &[i16]) -> i32 {
+    #[cfg(target_arch = "x86_64")]
+    {
+        if is_x86_feature_detected!("avx2") {
+            // SAFETY: we just checked that avx2 is available
+            return unsafe { avx2::output_sum(activation, accumulator, weights) };
+        }
+    }
+
+    output_sum_scalar(activation, accumulator, weights)
+}
+
+fn output_sum_scalar(activation: Activation, accumulator: &[i16; HIDDEN], weights: &[i16]) -> i32 {
+    let mut sum: i32 = 0;
+    for (&x, &w) in accumulator.iter().zip(weights) {
+        let v = x.clamp(0, QA as i16) as i32;
+        let term = match activation {
+            Activation::CReLU => v * w as i32,
+            // the product of the activation and the weight is truncated to 16
+            // bits to match the SIMD version, the trainer keeps output weights
+            // small enough that this is exact
+            Activation::SCReLU => (v * w as i32) as i16 as i32 * v,
+        };
+        sum = sum.wrapping_add(term);
+    }
+    sum
+}
+
+#[cfg(target_arch = "x86_64")]
+mod avx2 {
+    use super::{Activation, HIDDEN, QA};
+    use std::arch::x86_64::*;
+
+    #[target_feature(enable = "avx2")]
+    pub unsafe fn output_sum(
+        activation: Activation,
+        accumulator: &[i16; HIDDEN],
+        weights: &[i16],
+    ) -> i32 {
+        assert!(weights.len() >= HIDDEN);
+
+        let zero = _mm256_setzero_si256();
+        let qa = _mm256_set1_epi16(QA as i16);
+        let mut sum = _mm256_setzero_si256();
+
+        for i in (0..HIDDEN).step_by(16) {
+            let x = _mm256_loadu_si256(accumulator.as_ptr().add(i) as *const __m256i);
+            let w = _mm256_loadu_si256(weights.as_ptr().add(i) as *const __m256i);
+            let v = _mm256_min_epi16(_mm256_max_epi16(x, zero), qa);
+
+            let products = match activation {
+                Activation::CReLU => _mm256_madd_epi16(v, w),
+                Activation::SCReLU => _mm256_madd_epi16(_mm256_mullo_epi16(v, w), v),
+            };
+            sum = _mm256_add_epi32(sum, products);
+        }
+
+        let mut lanes = [0i32; 8];
+        _mm256_storeu_si256(lanes.as_mut_ptr() as *mut __m256i, sum);
+        lanes.iter().fold(0, |total: i32, &x| total.wrapping_add(x))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::chess_move::Move;
+    use crate::generate_moves::legal_moves;
+    use crate::{bench, fen, nnue::*};
+
+    // A network with small pseudo random weights, there is no real net to test
+    // against
+    fn test_network(activation: Activation) -> Network {
+        let mut state: u64 = 0x6e6e_7565;
+        let mut next = |range: i16| {
+            state = state
+                .wrapping_mul(6364136223846793005)
+                .wrapping_add(1442695040888963407);
+            ((state >> 33) % (2 * range as u64 + 1)) as i16 - range
+        };
+
+        Network {
+            activation,
+            feature_weights: (0..INPUTS * HIDDEN).map(|_| next(40)).collect(),
+            feature_bias: (0..HIDDEN).map(|_| next(100)).collect(),
+            output_weights: (0..2 * HIDDEN).map(|_| next(127)).collect(),
+            output_bias: next(1000),
+        }
+    }
+
+    #[test]
+    fn load_and_save() {
+        for activation in [Activation::CReLU, Activation::SCReLU] {
+            let network = test_network(activation);
+            let bytes = network.to_bytes();
+            assert_eq!(bytes.len(), NETWORK_SIZE);
+            assert!(Network::from_bytes(&bytes).unwrap() == network);
+        }
+    }
+
+    #[test]
+    fn load_errors() {
+        let bytes = test_network(Activation::SCReLU).to_bytes();
+
+        let mut bad = bytes.clone();
+        bad[0] = b'X';
+        assert!(matches!(
+            Network::from_bytes(&bad),
+            Err(NnueError::InvalidHeader)
+        ));
+        assert!(matches!(
+            Network::from_bytes(&bytes[..8]),
+            Err(NnueError::InvalidHeader)
+        ));
+
+        let mut bad = bytes.clone();
+        bad[4] = 2;
+        assert!(matches!(
+            Network::from_bytes(&bad),
+            Err(NnueError::UnsupportedVersion(2))
+        ));
+
+        let mut bad = bytes.clone();
+        bad[8] ^= 1;
+        assert!(matches!(
+            Network::from_bytes(&bad),
+            Err(NnueError::ArchitectureMismatch(_))
+        ));
+
+        assert!(matches!(
+            Network::from_bytes(&bytes[..bytes.len() - 2]),
+            Err(NnueError::InvalidSize { .. })
+        ));
+
+        assert!(matches!(
+            Network::load(std::path::Path::new("does/not/exist.nnue")),
+            Err(NnueError::Io(_))
+        ));
+    }
+
+    // Walk the move tree, checking that the incrementally updated accumulators
+    // always match ones built from scratch
+    fn check_updates(network: &Network, board: &Board, accumulator: &Accumulator, depth: u32) {
+        assert!(*accumulator == Accumulator::new(network, board));
+        if depth == 0 {
+            return;
+        }
+
+        let mut moves: Vec<Move> = Vec::new();
+        legal_moves(board, &mut moves);
+        for m in moves {
+            let mut child = *board;
+            let delta = child.make_move_with_delta(&m);
+            let mut child_accumulator = accumulator.clone();
+            child_accumulator.update(network, &delta);
+            check_updates(network, &child, &child_accumulator, depth - 1);
+        }
+    }
+
+    #[test]
+    fn embedded_network() {
+        let network = test_network(Activation::CReLU);
+        let bytes = network.to_bytes();
+
+        // a build without a network embeds an empty file
+        assert!(Network::from_embedded(&[]).is_none());
+        assert!(Network::from_embedded(&bytes).unwrap().unwrap() == network);
+        assert!(matches!(
+            Network::from_embedded(&bytes[1..]),
+            Some(Err(NnueError::InvalidHeader))
+        ));
+
+        assert_eq!(
+            Network::default_network().is_some(),
+            !DEFAULT_NETWORK.is_empty()
+        );
+        if let Some(default) = Network::default_network() {
+            assert!(default.is_ok());
+        }
+    }
+
+    #[test]
+    fn eval_file() {
+        let network = test_network(Activation::SCReLU);
+        let path =
+            std::env::temp_dir().join(format!("oxide-eval-file-{}.nnue", std::process::id()));
+        std::fs::write(&path, network.to_bytes()).unwrap();
+
+        let loaded = Network::from_eval_file(path.to_str().unwrap());
+        std::fs::remove_file(&path).unwrap();
+        assert!(loaded.unwrap().unwrap() == network);
+
+        assert!(matches!(
+            Network::from_eval_file("does/not/exist.nnue"),
+            Some(Err(NnueError::Io(_)))
+        ));
+        for default in [DEFAULT_EVAL_FILE, ""] {
+            assert_eq!(
+                Network::from_eval_file(default).is_some(),
+                Network::default_network().is_some()
+            );
+        }
+    }
+
+    #[test]
+    fn bench_identifies_network() {
+        let network = test_network(Activation::SCReLU);
+        let (positions, signature) = bench::run(|b| network.evaluate_board(b));
+        assert!(positions > bench::BENCH_FENS.len());
+        assert_eq!(bench::run(|b| network.evaluate_board(b)).1, signature);
+
+        // one changed weight is enough to change the signature
+        let mut other = network.clone();
+        other.output_weights[0] += 1;
+        assert_ne!(bench::run(|b| other.evaluate_board(b)).1, signature);
+
+        let crelu = test_network(Activation::CReLU);
+        assert_ne!(bench::run(|b| crelu.evaluate_board(b)).1, signature);
+    }
+
+    #[test]
+    fn incremental_updates() {
+        let network = test_network(Activation::SCReLU);
+        for fen in [
+            "r3k2r/p1ppqpb1/bn2pnp1/3PN3/1p2P3/2N2Q1p/PPPBBPPP/R3K2R w KQkq - 0 1",
+            // en passant and promotions with and without captures
+            "r3k2r/1P6/8/8/2Pp4/8/8/R3K2R b KQkq c3 0 1",
+            "n1n5/PPPk4/8/8/8/8/4Kppp/5N1N b - - 0 1",
+        ] {
+            let board = fen::fen_to_board(fen).unwrap();
+            check_updates(&network, &board, &Accumulator::new(&network, &board), 2);
+        }
+    }
+
+    #[test]
+    fn simd_matches_scalar() {
+        for activation in [Activation::CReLU, Activation::SCReLU] {
+            let network = test_network(activation);
+            let board = fen::fen_to_board(
+                "r3k2r/p1ppqpb1/bn2pnp1/3PN3/1p2P3/2N2Q1p/PPPBBPPP/R3K2R w KQkq - 0 1",
+            )
+            .unwrap();
+            let accumulator = Accumulator::new(&network, &board);
+
+            for side in [Side::White, Side::Black] {
+                let values = &accumulator.values[side as usize].0;
+                let weights = &network.output_weights[..HIDDEN];
+                assert_eq!(
+                    output_sum(activation, values, weights),
+                    output_sum_scalar(activation, values, weights)
+                );
+            }
+        }
+    }
+
+    #[test]
+    fn evaluation_is_symmetric() {
+        let network = test_network(Activation::SCReLU);
+        let board =
+            fen::fen_to_board("r1bqkbnr/pppp1ppp/2n5/4p3/4P3/5N2/PPPP1PPP/RNBQKB1R w KQkq - 2 3")
+                .unwrap();
+        let mirrored =
+            fen::fen_to_board("rnbqkb1r/pppp1ppp/5n2/4p3/4P3/2N5/PPPP1PPP/R1BQKBNR b KQkq - 2 3")
+                .unwrap();
+
+        let eval = network.evaluate(&Accumulator::new(&network, &board), board.stm);
+        let mirrored_eval = network.evaluate(&Accumulator::new(&network, &mirrored), mirrored.stm);
+        assert_eq!(eval, mirrored_eval);
+    }
+}
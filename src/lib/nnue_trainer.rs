@@ -0,0 +1,565 @@
+use crate::board::Board;
+use crate::definitions::*;
+use crate::nnue::{self, Activation, Network, HIDDEN, INPUTS, QA, QB, SCALE};
+use crate::training_data::{DataError, PackedReader, TrainingEntry};
+
+use arrayvec::ArrayVec;
+use std::io::Read;
+use strum::IntoEnumIterator;
+
+// The parameters are stored flat, in the same order as the network file
+const FEATURE_BIAS: usize = INPUTS * HIDDEN;
+const OUTPUT_WEIGHTS: usize = FEATURE_BIAS + HIDDEN;
+const OUTPUT_BIAS: usize = OUTPUT_WEIGHTS + 2 * HIDDEN;
+const PARAMS: usize = OUTPUT_BIAS + 1;
+
+/// Every weight is kept within this range, so that the quantised network fits
+/// in 16 bits. With SCReLU the product of an activation and an output weight
+/// must fit as well, which needs `|w| * QB <= i16::MAX / QA`.
+const WEIGHT_CLIP: f32 = 1.98;
+
+const ADAM_BETA1: f32 = 0.9;
+const ADAM_BETA2: f32 = 0.999;
+const ADAM_EPSILON: f32 = 1e-8;
+
+#[derive(Copy, Clone, Debug)]
+pub struct TrainerConfig {
+    pub activation: Activation,
+    pub learning_rate: f32,
+    /// Decoupled weight decay, as in AdamW.
+    pub weight_decay: f32,
+    /// How much of the target comes from the game result rather than the
+    /// score, between 0 and 1.
+    pub wdl: f32,
+    pub batch_size: usize,
+    pub threads: usize,
+    pub seed: u64,
+}
+
+impl Default for TrainerConfig {
+    fn default() -> Self {
+        TrainerConfig {
+            activation: Activation::SCReLU,
+            learning_rate: 0.001,
+            weight_decay: 0.01,
+            wdl: 0.25,
+            batch_size: 16384,
+            threads: std::thread::available_parallelism().map_or(1, |n| n.get()),
+            seed: 0x5eed,
+        }
+    }
+}
+
+/// A training position reduced to its active inputs.
+#[derive(Clone, Debug)]
+pub struct Sample {
+    /// The active inputs from the side to move's point of view, then the
+    /// other side's.
+    features: [ArrayVec<u16, 32>; 2],
+    /// The score and result from the side to move's point of view, the score
+    /// already passed through the sigmoid.
+    score: f32,
+    result: f32,
+}
+
+impl Sample {
+    /// The sample for a training entry. Positions with more than 32 pieces
+    /// are rejected, they can come from text data or a FEN but never from
+    /// packed data.
+    pub fn new(entry: &TrainingEntry) -> Result<Sample, DataError> {
+        let board = &entry.board;
+        let (score, result) = match board.stm {
+            Side::White => (entry.score as f32, entry.result.to_f64() as f32),
+            Side::Black => (-entry.score as f32, 1.0 - entry.result.to_f64() as f32),
+        };
+
+        Ok(Sample {
+            features: [
+                Sample::features(board, board.stm)?,
+                Sample::features(board, !board.stm)?,
+            ],
+            score: sigmoid(score / SCALE as f32),
+            result,
+        })
+    }
+
+    fn features(board: &Board, perspective: Side) -> Result<ArrayVec<u16, 32>, DataError> {
+        let mut features = ArrayVec::new();
+        for piece in Piece::iter() {
+            let mut bb = board.get_piece_bb(piece);
+            while bb != BB_EMPTY {
+                let feature = nnue::feature_index(perspective, piece, bb.poplsb());
+                features.try_push(feature as u16).map_err(|_| {
+                    DataError::Invalid(format!(
+                        "{} pieces on the board, a sample holds at most 32",
+                        board.occupied_squares().popcnt()
+                    ))
+                })?;
+            }
+        }
+        Ok(features)
+    }
+}
+
+/// Read packed training data.
+pub fn load_samples<R: Read>(reader: R) -> Result<Vec<Sample>, DataError> {
+    PackedReader::new(reader)
+        .map(|entry| entry.and_then(|e| Sample::new(&e)))
+        .collect()
+}
+
+fn sigmoid(x: f32) -> f32 {
+    1.0 / (1.0 + (-x).exp())
+}
+
+// The activation and its derivative
+fn activate(activation: Activation, x: f32) -> (f32, f32) {
+    let inside = x > 0.0 && x < 1.0;
+    let c = x.clamp(0.0, 1.0);
+    match activation {
+        Activation::CReLU => (c, if inside { 1.0 } else { 0.0 }),
+        Activation::SCReLU => (c * c, if inside { 2.0 * c } else { 0.0 }),
+    }
+}
+
+// A small xorshift generator for the initial weights and shuffling
+struct Rng(u64);
+
+impl Rng {
+    fn next(&mut self) -> u64 {
+        self.0 ^= self.0 << 13;
+        self.0 ^= self.0 >> 7;
+        self.0 ^= self.0 << 17;
+        self.0
+    }
+
+    // Uniform in [-range, range]
+    fn uniform(&mut self, range: f32) -> f32 {
+        let unit = (self.next() >> 40) as f32 / (1u64 << 24) as f32;
+        (2.0 * unit - 1.0) * range
+    }
+}
+
+/// Trains a network of the architecture in `nnue` in floating point. The
+/// prediction is `sigmoid(eval / SCALE)`, and the target interpolates between
+/// the game result and the score passed through the same sigmoid.
+pub struct Trainer {
+    config: TrainerConfig,
+    params: Vec<f32>,
+    // AdamW moment estimates
+    momentum: Vec<f32>,
+    velocity: Vec<f32>,
+    steps: i32,
+    // One gradient buffer for each thread
+    gradients: Vec<Vec<f32>>,
+    rng: Rng,
+}
+
+impl Trainer {
+    /// Start from small random weights.
+    pub fn new(config: TrainerConfig) -> Trainer {
+        let mut rng = Rng(config.seed | 1);
+        let feature_range = 1.0 / (32.0f32).sqrt();
+        let output_range = 1.0 / (2.0 * HIDDEN as f32).sqrt();
+
+        let mut params = vec![0.0; PARAMS];
+        for w in &mut params[..FEATURE_BIAS] {
+            *w = rng.uniform(feature_range);
+        }
+        for w in &mut params[OUTPUT_WEIGHTS..OUTPUT_BIAS] {
+            *w = rng.uniform(output_range);
+        }
+
+        Trainer {
+            config,
+            params,
+            momentum: vec![0.0; PARAMS],
+            velocity: vec![0.0; PARAMS],
+            steps: 0,
+            gradients: vec![vec![0.0; PARAMS]; config.threads.max(1)],
+            rng,
+        }
+    }
+
+    // The network output for a sample, in units of SCALE centipawns, filling in
+    // the hidden layer for each side
+    fn forward(&self, sample: &Sample, hidden: &mut [[f32; HIDDEN]; 2]) -> f32 {
+        let mut out = self.params[OUTPUT_BIAS];
+        for (side, values) in hidden.iter_mut().enumerate() {
+            values.copy_from_slice(&self.params[FEATURE_BIAS..OUTPUT_WEIGHTS]);
+            for &f in &sample.features[side] {
+                let f = f as usize * HIDDEN;
+                for (v, w) in values.iter_mut().zip(&self.params[f..f + HIDDEN]) {
+                    *v += w;
+                }
+            }
+
+            let weights = &self.params[OUTPUT_WEIGHTS + side * HIDDEN..][..HIDDEN];
+            for (&v, w) in values.iter().zip(weights) {
+                out += activate(self.config.activation, v).0 * w;
+            }
+        }
+        out
+    }
+
+    fn target(&self, sample: &Sample) -> f32 {
+        self.config.wdl * sample.result + (1.0 - self.config.wdl) * sample.score
+    }
+
+    /// The evaluation of a sample in centipawns, from the side to move's point
+    /// of view.
+    pub fn evaluate(&self, sample: &Sample) -> f32 {
+        self.forward(sample, &mut [[0.0; HIDDEN]; 2]) * SCALE as f32
+    }
+
+    /// The mean squared error of the predictions.
+    pub fn loss(&self, samples: &[Sample]) -> f64 {
+        let mut hidden = [[0.0; HIDDEN]; 2];
+        let total: f64 = samples
+            .iter()
+            .map(|s| {
+                let error = sigmoid(self.forward(s, &mut hidden)) - self.target(s);
+                (error * error) as f64
+            })
+            .sum();
+        total / samples.len() as f64
+    }
+
+    // Add the gradient of the summed squared error over the samples, returning
+    // the summed error
+    fn backward(&self, samples: &[Sample], gradient: &mut [f32]) -> f64 {
+        let mut hidden = [[0.0; HIDDEN]; 2];
+        let mut total = 0.0;
+
+        for sample in samples {
+            let p = sigmoid(self.forward(sample, &mut hidden));
+            let error = p - self.target(sample);
+            total += (error * error) as f64;
+
+            // derivative with respect to the network output
+            let g = 2.0 * error * p * (1.0 - p);
+            gradient[OUTPUT_BIAS] += g;
+
+            for (side, values) in hidden.iter().enumerate() {
+                let offset = OUTPUT_WEIGHTS + side * HIDDEN;
+                let weights = &self.params[offset..offset + HIDDEN];
+                let mut deltas = [0.0; HIDDEN];
+                for (((delta, &v), &w), dw) in deltas
+                    .iter_mut()
+                    .zip(values)
+                    .zip(weights)
+                    .zip(&mut gradient[offset..offset + HIDDEN])
+                {
+                    let (a, da) = activate(self.config.activation, v);
+                    *dw += g * a;
+                    *delta = g * w * da;
+                }
+
+                for (db, delta) in gradient[FEATURE_BIAS..OUTPUT_WEIGHTS]
+                    .iter_mut()
+                    .zip(&deltas)
+                {
+                    *db += delta;
+                }
+                for &f in &sample.features[side] {
+                    let f = f as usize * HIDDEN;
+                    for (dw, delta) in gradient[f..f + HIDDEN].iter_mut().zip(&deltas) {
+                        *dw += delta;
+                    }
+                }
+            }
+        }
+        total
+    }
+
+    // Compute the gradient of a batch across the threads, leaving the sum in
+    // the first buffer
+    fn batch_gradient(&mut self, batch: &[Sample]) -> f64 {
+        let chunk_size = batch.len().div_ceil(self.gradients.len()).max(1);
+        let mut gradients = std::mem::take(&mut self.gradients);
+
+        let trainer = &*self;
+        let total = std::thread::scope(|scope| {
+            let handles: Vec<_> = batch
+                .chunks(chunk_size)
+                .zip(gradients.iter_mut())
+                .map(|(chunk, gradient)| {
+                    gradient.fill(0.0);
+                    scope.spawn(move || trainer.backward(chunk, gradient))
+                })
+                .collect();
+            handles.into_iter().map(|h| h.join().unwrap()).sum::<f64>()
+        });
+
+        let used = batch.len().div_ceil(chunk_size);
+        let (first, rest) = gradients.split_at_mut(1);
+        for other in &rest[..used - 1] {
+            for (g, o) in first[0].iter_mut().zip(other) {
+                *g += o;
+            }
+        }
+
+        self.gradients = gradients;
+        total
+    }
+
+    // One AdamW step with the gradient in the first buffer
+    fn step(&mut self, batch_size: usize) {
+        self.steps += 1;
+        let TrainerConfig {
+            learning_rate,
+            weight_decay,
+            ..
+        } = self.config;
+        let correction1 = 1.0 - ADAM_BETA1.powi(self.steps);
+        let correction2 = 1.0 - ADAM_BETA2.powi(self.steps);
+
+        let params = self
+            .params
+            .iter_mut()
+            .zip(&mut self.momentum)
+            .zip(&mut self.velocity)
+            .zip(&self.gradients[0]);
+        for (((param, momentum), velocity), g) in params {
+            let g = g / batch_size as f32;
+            *momentum = ADAM_BETA1 * *momentum + (1.0 - ADAM_BETA1) * g;
+            *velocity = ADAM_BETA2 * *velocity + (1.0 - ADAM_BETA2) * g * g;
+
+            let m = *momentum / correction1;
+            let v = *velocity / correction2;
+            *param *= 1.0 - learning_rate * weight_decay;
+            *param -= learning_rate * m / (v.sqrt() + ADAM_EPSILON);
+            *param = param.clamp(-WEIGHT_CLIP, WEIGHT_CLIP);
+        }
+    }
+
+    /// Shuffle the samples and train on each batch once, returning the mean
+    /// squared error seen during the epoch.
+    pub fn train_epoch(&mut self, samples: &mut [Sample]) -> f64 {
+        for i in (1..samples.len()).rev() {
+            let j = (self.rng.next() % (i as u64 + 1)) as usize;
+            samples.swap(i, j);
+        }
+
+        let mut total = 0.0;
+        for batch in samples.chunks(self.config.batch_size) {
+            total += self.batch_gradient(batch);
+            self.step(batch.len());
+        }
+        total / samples.len() as f64
+    }
+
+    /// Quantise the weights into a network the engine can load.
+    pub fn network(&self) -> Network {
+        let quantise = |values: &[f32], scale: i32| -> Vec<i16> {
+            values
+                .iter()
+                .map(|v| {
+                    (v * scale as f32)
+                        .round()
+                        .clamp(i16::MIN as f32, i16::MAX as f32) as i16
+                })
+                .collect()
+        };
+
+        Network {
+            activation: self.config.activation,
+            feature_weights: quantise(&self.params[..FEATURE_BIAS], QA),
+            feature_bias: quantise(&self.params[FEATURE_BIAS..OUTPUT_WEIGHTS], QA),
+            output_weights: quantise(&self.params[OUTPUT_WEIGHTS..OUTPUT_BIAS], QB),
+            output_bias: quantise(&self.params[OUTPUT_BIAS..], QA * QB)[0],
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::chess_move::Move;
+    use crate::fen;
+    use crate::generate_moves::legal_moves;
+    use crate::nnue::Accumulator;
+    use crate::nnue_trainer::*;
+    use crate::training_data::GameResult;
+
+    // Positions from a pseudo random game, labelled with a made up score that
+    // a network can learn: the material balance
+    fn test_entries(count: usize) -> Vec<TrainingEntry> {
+        let mut rng = Rng(0x1234_5678);
+        let mut entries = Vec::new();
+        let mut board =
+            fen::fen_to_board("rnbqkbnr/pppppppp/8/8/8/8/PPPPPPPP/RNBQKBNR w KQkq - 0 1").unwrap();
+
+        while entries.len() < count {
+            let material: i32 = [100, 300, 300, 500, 900, 0]
+                .iter()
+                .enumerate()
+                .map(|(i, v)| {
+                    let white = board.get_piece_bb(Piece::iter().nth(i).unwrap());
+                    let black = board.get_piece_bb(Piece::iter().nth(i + 6).unwrap());
+                    v * (white.popcnt() as i32 - black.popcnt() as i32)
+                })
+                .sum();
+            let result = match material {
+                m if m > 200 => GameResult::WhiteWin,
+                m if m < -200 => GameResult::BlackWin,
+                _ => GameResult::Draw,
+            };
+            entries.push(TrainingEntry {
+                board,
+                score: material as i16,
+                result,
+            });
+
+            let mut moves: Vec<Move> = Vec::new();
+            legal_moves(&board, &mut moves);
+            if moves.is_empty() || board.halfmove_clock >= 100 {
+                board =
+                    fen::fen_to_board("rnbqkbnr/pppppppp/8/8/8/8/PPPPPPPP/RNBQKBNR w KQkq - 0 1")
+                        .unwrap();
+            } else {
+                board.make_move(&moves[rng.next() as usize % moves.len()]);
+            }
+        }
+        entries
+    }
+
+    fn config(activation: Activation) -> TrainerConfig {
+        TrainerConfig {
+            activation,
+            learning_rate: 0.01,
+            batch_size: 64,
+            threads: 3,
+            ..TrainerConfig::default()
+        }
+    }
+
+    #[test]
+    fn gradient_matches_finite_differences() {
+        let samples: Vec<Sample> = test_entries(8)
+            .iter()
+            .map(|e| Sample::new(e).unwrap())
+            .collect();
+        for activation in [Activation::CReLU, Activation::SCReLU] {
+            let mut trainer = Trainer::new(config(activation));
+            let mut gradient = vec![0.0; PARAMS];
+            trainer.backward(&samples, &mut gradient);
+
+            // parameters that are used by every sample, in each part of the
+            // network
+            let white_pawn_e2 = nnue::feature_index(Side::White, Piece::WhitePawn, Square::E2);
+            for i in [
+                white_pawn_e2 * HIDDEN + 3,
+                FEATURE_BIAS + 7,
+                OUTPUT_WEIGHTS + 11,
+                OUTPUT_WEIGHTS + HIDDEN + 5,
+                OUTPUT_BIAS,
+            ] {
+                let original = trainer.params[i];
+                let epsilon = 1e-2;
+                trainer.params[i] = original + epsilon;
+                let above = trainer.loss(&samples) * samples.len() as f64;
+                trainer.params[i] = original - epsilon;
+                let below = trainer.loss(&samples) * samples.len() as f64;
+                trainer.params[i] = original;
+
+                let numeric = (above - below) / (2.0 * epsilon as f64);
+                let analytic = gradient[i] as f64;
+                assert!(
+                    (numeric - analytic).abs() <= 1e-3 + 0.05 * analytic.abs(),
+                    "parameter {i}: numeric {numeric}, analytic {analytic}"
+                );
+            }
+        }
+    }
+
+    #[test]
+    fn threads_agree() {
+        let samples: Vec<Sample> = test_entries(50)
+            .iter()
+            .map(|e| Sample::new(e).unwrap())
+            .collect();
+        let single = Trainer::new(TrainerConfig {
+            threads: 1,
+            ..config(Activation::SCReLU)
+        });
+        let mut expected = vec![0.0; PARAMS];
+        single.backward(&samples, &mut expected);
+
+        let mut multi = Trainer::new(config(Activation::SCReLU));
+        multi.batch_gradient(&samples);
+        for (a, b) in expected.iter().zip(&multi.gradients[0]) {
+            assert!((a - b).abs() <= 1e-4 * (1.0 + a.abs()));
+        }
+    }
+
+    #[test]
+    fn training_reduces_loss() {
+        let mut samples: Vec<Sample> = test_entries(256)
+            .iter()
+            .map(|e| Sample::new(e).unwrap())
+            .collect();
+        let mut trainer = Trainer::new(config(Activation::SCReLU));
+
+        let before = trainer.loss(&samples);
+        for _ in 0..10 {
+            trainer.train_epoch(&mut samples);
+        }
+        let after = trainer.loss(&samples);
+        assert!(after < before / 2.0, "loss went from {before} to {after}");
+    }
+
+    #[test]
+    fn quantised_network_matches() {
+        let entries = test_entries(64);
+        for activation in [Activation::CReLU, Activation::SCReLU] {
+            let mut samples: Vec<Sample> =
+                entries.iter().map(|e| Sample::new(e).unwrap()).collect();
+            let mut trainer = Trainer::new(config(activation));
+            for _ in 0..3 {
+                trainer.train_epoch(&mut samples);
+            }
+
+            let network = trainer.network();
+            let network = Network::from_bytes(&network.to_bytes()).unwrap();
+
+            // compare against the float network with the rounded weights, so
+            // that only the integer arithmetic differs
+            let dequantise = |values: &[i16], scale: i32| -> Vec<f32> {
+                values.iter().map(|&v| v as f32 / scale as f32).collect()
+            };
+            trainer.params = [
+                dequantise(&network.feature_weights, QA),
+                dequantise(&network.feature_bias, QA),
+                dequantise(&network.output_weights, QB),
+                dequantise(&[network.output_bias], QA * QB),
+            ]
+            .concat();
+
+            for entry in &entries {
+                let expected = trainer.evaluate(&Sample::new(entry).unwrap());
+                let accumulator = Accumulator::new(&network, &entry.board);
+                let actual = network.evaluate(&accumulator, entry.board.stm);
+                assert!(
+                    (expected - actual as f32).abs() <= 1.5,
+                    "float {expected}, quantised {actual}"
+                );
+            }
+        }
+    }
+
+    #[test]
+    fn too_many_pieces() {
+        // 33 pieces, which a text entry can hold but a sample can't
+        let entry = TrainingEntry::from_text(
+            "rnbqkbnr/pppppppp/8/8/4N3/8/PPPPPPPP/RNBQKBNR w KQkq - 0 1 | 0 | 0.5",
+        )
+        .unwrap();
+        assert!(matches!(Sample::new(&entry), Err(DataError::Invalid(_))));
+
+        let entry = TrainingEntry::from_text(
+            "rnbqkbnr/pppppppp/8/8/8/8/PPPPPPPP/RNBQKBNR w KQkq - 0 1 | 0 | 0.5",
+        )
+        .unwrap();
+        assert!(Sample::new(&entry).is_ok());
+    }
+}
@@ -0,0 +1,392 @@
+use crate::board::Board;
+use crate::definitions::*;
+use crate::evaluation::Score;
+use crate::trace::{NoTrace, Param, Trace};
+
+use strum::EnumCount;
+
+// Pawn structure terms, the rank arrays are indexed by the rank of the pawn
+// relative to its own side
+pub const PASSED_PAWN: [Score; Rank::COUNT] = [
+    Score(0, 0),
+    Score(-2, 8),
+    Score(-5, 12),
+    Score(-3, 30),
+    Score(17, 55),
+    Score(45, 110),
+    Score(80, 170),
+    Score(0, 0),
+];
+pub const CANDIDATE_PASSER: [Score; Rank::COUNT] = [
+    Score(0, 0),
+    Score(-3, 4),
+    Score(0, 6),
+    Score(4, 12),
+    Score(12, 22),
+    Score(20, 40),
+    Score(0, 0),
+    Score(0, 0),
+];
+pub const CONNECTED_PAWN: [Score; Rank::COUNT] = [
+    Score(0, 0),
+    Score(3, 0),
+    Score(6, 2),
+    Score(8, 4),
+    Score(16, 12),
+    Score(30, 30),
+    Score(50, 50),
+    Score(0, 0),
+];
+pub const PHALANX_PAWN: [Score; Rank::COUNT] = [
+    Score(0, 0),
+    Score(2, 1),
+    Score(5, 3),
+    Score(9, 6),
+    Score(18, 16),
+    Score(40, 40),
+    Score(70, 80),
+    Score(0, 0),
+];
+pub const ISOLATED_PAWN: Score = Score(-8, -12);
+pub const DOUBLED_PAWN: Score = Score(-10, -25);
+pub const BACKWARD_PAWN: Score = Score(-9, -12);
+
+// Passed pawn terms that depend on more than the pawns, which can't be cached
+pub const PASSED_PAWN_BLOCKED: [Score; Rank::COUNT] = [
+    Score(0, 0),
+    Score(0, 0),
+    Score(0, 0),
+    Score(-2, -6),
+    Score(-6, -16),
+    Score(-12, -35),
+    Score(-20, -60),
+    Score(0, 0),
+];
+pub const PASSED_PAWN_OUR_KING_DISTANCE: Score = Score(0, -3);
+pub const PASSED_PAWN_THEIR_KING_DISTANCE: Score = Score(0, 6);
+pub const UNSTOPPABLE_PASSER: Score = Score(0, 600);
+
+/// Squares in front of a pawn on the same file.
+/// the first index is the color, the second index is the square.
+pub const FORWARD_FILE_BB: [[BB; Square::COUNT]; Side::COUNT] = {
+    let mut bb = [[BB_EMPTY; Square::COUNT]; Side::COUNT];
+    let mut i = 0;
+    while i < Square::COUNT {
+        bb[Side::White as usize][i].0 =
+            FILE_BB[i % 8].0 & FORWARD_RANKS_BB[Side::White as usize][i / 8].0;
+        bb[Side::Black as usize][i].0 =
+            FILE_BB[i % 8].0 & FORWARD_RANKS_BB[Side::Black as usize][i / 8].0;
+        i += 1;
+    }
+    bb
+};
+
+/// Squares in front of a pawn on the adjacent files, i.e all the squares the
+/// pawn could ever attack as it advances.
+/// the first index is the color, the second index is the square.
+pub const PAWN_ATTACK_SPAN: [[BB; Square::COUNT]; Side::COUNT] = {
+    let mut bb = [[BB_EMPTY; Square::COUNT]; Side::COUNT];
+    let mut i = 0;
+    while i < Square::COUNT {
+        bb[Side::White as usize][i].0 =
+            ADJACENT_FILES_BB[i % 8].0 & FORWARD_RANKS_BB[Side::White as usize][i / 8].0;
+        bb[Side::Black as usize][i].0 =
+            ADJACENT_FILES_BB[i % 8].0 & FORWARD_RANKS_BB[Side::Black as usize][i / 8].0;
+        i += 1;
+    }
+    bb
+};
+
+/// If there are no enemy pawns in this mask, the pawn is passed.
+/// the first index is the color, the second index is the square.
+///
+/// # Examples
+/// ```
+/// # use oxide::{definitions::*, pawns::*};
+/// let mask = PASSED_PAWN_MASK[Side::White as usize][Square::B6 as usize];
+/// assert_eq!(mask.popcnt(), 6);
+/// ```
+pub const PASSED_PAWN_MASK: [[BB; Square::COUNT]; Side::COUNT] = {
+    let mut bb = [[BB_EMPTY; Square::COUNT]; Side::COUNT];
+    let mut i = 0;
+    while i < Square::COUNT {
+        bb[Side::White as usize][i].0 = FORWARD_FILE_BB[Side::White as usize][i].0
+            | PAWN_ATTACK_SPAN[Side::White as usize][i].0;
+        bb[Side::Black as usize][i].0 = FORWARD_FILE_BB[Side::Black as usize][i].0
+            | PAWN_ATTACK_SPAN[Side::Black as usize][i].0;
+        i += 1;
+    }
+    bb
+};
+
+/// The cached result of evaluating the pawn structure.
+#[derive(Copy, Clone, Debug, PartialEq)]
+pub struct PawnEntry {
+    pub key: u64,
+    /// Score of the terms that only depend on the pawns, from white's point of view
+    pub score: Score,
+    /// The passed pawns of each side
+    pub passed: [BB; Side::COUNT],
+}
+
+/// A cache of pawn structure evaluations indexed by the board's pawn key. Pawn
+/// structures change rarely during a search, so almost every probe hits.
+pub struct PawnHashTable {
+    entries: Vec<Option<PawnEntry>>,
+}
+
+impl PawnHashTable {
+    /// Create a table with `size` entries, which is rounded up to a power of two.
+    pub fn new(size: usize) -> PawnHashTable {
+        PawnHashTable {
+            entries: vec![None; size.max(1).next_power_of_two()],
+        }
+    }
+
+    pub fn clear(&mut self) {
+        self.entries.fill(None);
+    }
+
+    /// Look up the pawn structure of the board, evaluating and storing it if
+    /// the table does not have it.
+    pub fn probe(&mut self, board: &Board) -> PawnEntry {
+        let key = board.pawn_key();
+        let index = key as usize & (self.entries.len() - 1);
+
+        match self.entries[index] {
+            Some(entry) if entry.key == key => entry,
+            _ => {
+                let entry = evaluate_pawn_structure(board, &mut NoTrace);
+                self.entries[index] = Some(entry);
+                entry
+            }
+        }
+    }
+}
+
+/// Evaluate everything about the pawn structure that only depends on the
+/// position of the pawns.
+pub fn evaluate_pawn_structure<T: Trace>(board: &Board, trace: &mut T) -> PawnEntry {
+    let (white, white_passed) = evaluate_side(board, Side::White, trace);
+    let (black, black_passed) = evaluate_side(board, Side::Black, trace);
+
+    PawnEntry {
+        key: board.pawn_key(),
+        score: white - black,
+        passed: [white_passed, black_passed],
+    }
+}
+
+fn evaluate_side<T: Trace>(board: &Board, us: Side, trace: &mut T) -> (Score, BB) {
+    let our_pawns = board.get_piece_bb(Piece::from_type(PieceType::Pawn, us));
+    let their_pawns = board.get_piece_bb(Piece::from_type(PieceType::Pawn, !us));
+
+    let mut score = Score::default();
+    let mut passed = BB_EMPTY;
+
+    let mut pawns = our_pawns;
+    while pawns != BB_EMPTY {
+        let sq = pawns.poplsb();
+        let rank = sq.relative_rank(us) as usize;
+        let file = sq.file() as usize;
+        let stop = stop_square(sq, us);
+
+        let opposed = FORWARD_FILE_BB[us as usize][sq as usize] & their_pawns != BB_EMPTY;
+        let doubled = FORWARD_FILE_BB[us as usize][sq as usize] & our_pawns != BB_EMPTY;
+        let neighbours = ADJACENT_FILES_BB[file] & our_pawns;
+        let phalanx = neighbours & RANK_BB[sq.rank() as usize];
+        let supporters = PAWN_ATTACKS[!us as usize][sq as usize] & our_pawns;
+
+        // a pawn is backward if all its neighbours are in front of it, and it
+        // can't advance without being captured
+        let backward = neighbours != BB_EMPTY
+            && neighbours & !FORWARD_RANKS_BB[us as usize][sq.rank() as usize] == BB_EMPTY
+            && PAWN_ATTACKS[us as usize][stop as usize] & their_pawns != BB_EMPTY;
+
+        if doubled {
+            score += DOUBLED_PAWN;
+            trace.add(Param::DoubledPawn, 0, us, 1);
+        }
+
+        if neighbours == BB_EMPTY {
+            score += ISOLATED_PAWN;
+            trace.add(Param::IsolatedPawn, 0, us, 1);
+        } else if backward {
+            score += BACKWARD_PAWN;
+            trace.add(Param::BackwardPawn, 0, us, 1);
+        }
+
+        if supporters != BB_EMPTY || phalanx != BB_EMPTY {
+            score += CONNECTED_PAWN[rank];
+            trace.add(Param::ConnectedPawn, rank, us, 1);
+        }
+
+        if phalanx != BB_EMPTY {
+            score += PHALANX_PAWN[rank];
+            trace.add(Param::PhalanxPawn, rank, us, 1);
+        }
+
+        // only the front pawn of doubled pawns can be passed
+        if doubled {
+            continue;
+        }
+
+        if PASSED_PAWN_MASK[us as usize][sq as usize] & their_pawns == BB_EMPTY {
+            score += PASSED_PAWN[rank];
+            trace.add(Param::PassedPawn, rank, us, 1);
+            passed |= sq.to_bb();
+        } else if !opposed {
+            // a candidate passer has enough friendly pawns on the adjacent files
+            // to force its way past the enemy pawns that guard its path
+            let sentries = PAWN_ATTACK_SPAN[us as usize][sq as usize] & their_pawns;
+            let helpers = neighbours & !FORWARD_RANKS_BB[us as usize][sq.rank() as usize];
+            if helpers.popcnt() >= sentries.popcnt() {
+                score += CANDIDATE_PASSER[rank];
+                trace.add(Param::CandidatePasser, rank, us, 1);
+            }
+        }
+    }
+
+    (score, passed)
+}
+
+/// Evaluate the passed pawns found by `evaluate_pawn_structure` in the context
+/// of the rest of the board, from white's point of view.
+pub fn evaluate_passed_pawns<T: Trace>(
+    board: &Board,
+    passed: &[BB; Side::COUNT],
+    trace: &mut T,
+) -> Score {
+    evaluate_passers_for(board, Side::White, passed[Side::White as usize], trace)
+        - evaluate_passers_for(board, Side::Black, passed[Side::Black as usize], trace)
+}
+
+fn evaluate_passers_for<T: Trace>(board: &Board, us: Side, mut passed: BB, trace: &mut T) -> Score {
+    let mut score = Score::default();
+    let occupied = board.occupied_squares();
+    let our_king = board.get_king(us);
+    let their_king = board.get_king(!us);
+
+    // in a pawn ending we can tell if the enemy king can catch the pawn
+    let their_pieces = board.get_pieces(!us)
+        ^ board.get_piece_bb(Piece::from_type(PieceType::Pawn, !us))
+        ^ their_king.to_bb();
+    let pawn_ending = their_pieces == BB_EMPTY;
+
+    while passed != BB_EMPTY {
+        let sq = passed.poplsb();
+        let rank = sq.relative_rank(us) as usize;
+        let stop = stop_square(sq, us);
+
+        if occupied & stop.to_bb() != BB_EMPTY {
+            score += PASSED_PAWN_BLOCKED[rank];
+            trace.add(Param::PassedPawnBlocked, rank, us, 1);
+        }
+
+        // the king distances matter more the further the pawn is advanced
+        let weight = rank.saturating_sub(2) as i32;
+        let our_distance = our_king.distance(stop) as i32 * weight;
+        let their_distance = their_king.distance(stop) as i32 * weight;
+        score += PASSED_PAWN_OUR_KING_DISTANCE * our_distance;
+        score += PASSED_PAWN_THEIR_KING_DISTANCE * their_distance;
+        trace.add(Param::PassedPawnOurKingDistance, 0, us, our_distance);
+        trace.add(Param::PassedPawnTheirKingDistance, 0, us, their_distance);
+
+        if pawn_ending && FORWARD_FILE_BB[us as usize][sq as usize] & occupied == BB_EMPTY {
+            // rule of the square, a pawn on its starting rank can move two squares
+            let promotion = promotion_square(sq, us);
+            let pawn_distance = (7 - rank).min(5) as u32;
+            let tempo = if board.stm == us { 0 } else { 1 };
+            if their_king.distance(promotion) > pawn_distance + tempo {
+                score += UNSTOPPABLE_PASSER;
+                trace.add(Param::UnstoppablePasser, 0, us, 1);
+            }
+        }
+    }
+
+    score
+}
+
+const fn stop_square(sq: Square, s: Side) -> Square {
+    match s {
+        Side::White => Square::from_index(sq as usize + 8),
+        Side::Black => Square::from_index(sq as usize - 8),
+    }
+}
+
+const fn promotion_square(sq: Square, s: Side) -> Square {
+    match s {
+        Side::White => Square::from_coord(sq.file(), Rank::Eight),
+        Side::Black => Square::from_coord(sq.file(), Rank::One),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::{fen, pawns::*};
+
+    fn structure(fen: &str) -> PawnEntry {
+        evaluate_pawn_structure(&fen::fen_to_board(fen).unwrap(), &mut NoTrace)
+    }
+
+    #[test]
+    fn pawn_terms() {
+        // isolated pawn on a2 against nothing, it is also passed
+        let entry = structure("4k3/8/8/8/8/8/P7/4K3 w - - 0 1");
+        assert_eq!(entry.score, ISOLATED_PAWN + PASSED_PAWN[1]);
+        assert_eq!(entry.passed[Side::White as usize], Square::A2.to_bb());
+
+        // doubled isolated pawns, only the front pawn is passed
+        let entry = structure("4k3/8/8/8/P7/P7/8/4K3 w - - 0 1");
+        assert_eq!(
+            entry.score,
+            ISOLATED_PAWN * 2 + DOUBLED_PAWN + PASSED_PAWN[3]
+        );
+        assert_eq!(entry.passed[Side::White as usize], Square::A4.to_bb());
+
+        // the c3 pawn supports the d4 pawn, but is backward as c4 is attacked
+        let entry = structure("4k3/8/8/1p6/3P4/2P5/8/4K3 w - - 0 1");
+        assert_eq!(entry.passed[Side::White as usize], Square::D4.to_bb());
+        assert_eq!(
+            entry.score,
+            BACKWARD_PAWN + CONNECTED_PAWN[3] + PASSED_PAWN[3] - ISOLATED_PAWN
+        );
+
+        // a phalanx of two, with a candidate passer against a single sentry
+        let entry = structure("4k3/8/1p6/8/2PP4/8/8/4K3 w - - 0 1");
+        assert_eq!(entry.passed[Side::White as usize], Square::D4.to_bb());
+        assert_eq!(
+            entry.score,
+            (CONNECTED_PAWN[3] + PHALANX_PAWN[3]) * 2 + CANDIDATE_PASSER[3] + PASSED_PAWN[3]
+                - ISOLATED_PAWN
+        );
+    }
+
+    #[test]
+    fn unstoppable_passer() {
+        let board = fen::fen_to_board("7k/8/8/8/8/8/P7/4K3 w - - 0 1").unwrap();
+        let passed = evaluate_pawn_structure(&board, &mut NoTrace).passed;
+        let score = evaluate_passed_pawns(&board, &passed, &mut NoTrace);
+        assert!(score.eg() >= UNSTOPPABLE_PASSER.eg());
+
+        // with black to move the king is in the square of the pawn
+        let board = fen::fen_to_board("4k3/8/8/8/8/8/P7/4K3 b - - 0 1").unwrap();
+        let score = evaluate_passed_pawns(&board, &passed, &mut NoTrace);
+        assert!(score.eg() < UNSTOPPABLE_PASSER.eg());
+    }
+
+    #[test]
+    fn pawn_hash_table() {
+        let mut table = PawnHashTable::new(1000);
+        let board = fen::fen_to_board(
+            "r3k2r/p1ppqpb1/bn2pnp1/3PN3/1p2P3/2N2Q1p/PPPBBPPP/R3K2R w KQkq - 0 1",
+        )
+        .unwrap();
+        let entry = table.probe(&board);
+        assert_eq!(entry, evaluate_pawn_structure(&board, &mut NoTrace));
+        assert_eq!(table.probe(&board), entry);
+
+        table.clear();
+        assert_eq!(table.probe(&board), entry);
+    }
+}
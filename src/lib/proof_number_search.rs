@@ -0,0 +1,289 @@
+use crate::board::Board;
+use crate::chess_move::Move;
+use crate::definitions::*;
+use crate::generate_moves::{is_in_check, legal_moves};
+
+const INFINITY: u32 = u32::MAX;
+
+/// A proof that the attacker forces checkmate. At the attacker's turn there is
+/// exactly one child, the winning move. At the defender's turn there is one
+/// child for every legal reply. A node with no children is checkmate.
+#[derive(Debug, Clone)]
+pub struct ProofTree {
+    pub children: Vec<(Move, ProofTree)>,
+}
+
+impl ProofTree {
+    /// The length of the longest line in the tree, in plies.
+    pub fn depth(&self) -> u32 {
+        self.children
+            .iter()
+            .map(|(_, child)| child.depth() + 1)
+            .max()
+            .unwrap_or(0)
+    }
+}
+
+#[derive(Debug, Clone)]
+pub enum PnResult {
+    /// The side to move forces checkmate.
+    Proven(ProofTree),
+    /// The side to move cannot force checkmate, the defender can always reach
+    /// a stalemate, a fifty move draw, or mate the attacker instead.
+    Disproven,
+    /// The memory limit was reached before the root was solved.
+    Unknown,
+}
+
+struct Node {
+    board: Board,
+    parent: Option<usize>,
+    // (move, node index) pairs, empty until the node is expanded
+    children: Vec<(Move, usize)>,
+    expanded: bool,
+    proof: u32,
+    disproof: u32,
+}
+
+impl Node {
+    fn new(board: Board, parent: Option<usize>) -> Node {
+        Node {
+            board,
+            parent,
+            children: Vec::new(),
+            expanded: false,
+            proof: 1,
+            disproof: 1,
+        }
+    }
+}
+
+/// Try to prove that the side to move can force checkmate using proof-number
+/// search. Unlike a depth first search, the tree is grown towards whichever
+/// leaf is cheapest to resolve, which finds long forced mates with narrow
+/// defences much more quickly.
+///
+/// The whole tree is kept in memory, `max_nodes` bounds its size. If the root is
+/// not solved by then, `PnResult::Unknown` is returned. Repetitions are not
+/// detected, but positions where the fifty move rule applies are draws. A proof
+/// is not necessarily the shortest mate, use `mate_search` for that.
+pub fn pn_search(board: &Board, max_nodes: usize) -> PnResult {
+    let mut search = PnSearch {
+        attacker: board.stm,
+        nodes: Vec::new(),
+    };
+
+    search.nodes.push(Node::new(*board, None));
+    search.evaluate(0);
+
+    while search.nodes[0].proof != 0 && search.nodes[0].disproof != 0 {
+        if search.nodes.len() >= max_nodes {
+            return PnResult::Unknown;
+        }
+
+        let mpn = search.select_most_proving();
+        search.expand(mpn);
+        search.update_ancestors(mpn);
+    }
+
+    if search.nodes[0].proof == 0 {
+        PnResult::Proven(search.proof_tree(0))
+    } else {
+        PnResult::Disproven
+    }
+}
+
+struct PnSearch {
+    attacker: Side,
+    nodes: Vec<Node>,
+}
+
+impl PnSearch {
+    fn is_or_node(&self, index: usize) -> bool {
+        self.nodes[index].board.stm == self.attacker
+    }
+
+    /// Set the proof and disproof numbers of a new leaf, solving it immediately
+    /// if the game is over.
+    fn evaluate(&mut self, index: usize) {
+        let board = &self.nodes[index].board;
+
+        let mut moves = arrayvec::ArrayVec::<Move, 256>::new();
+        legal_moves(board, &mut moves);
+
+        let (proof, disproof) = if moves.is_empty() {
+            if is_in_check(board, board.stm) && board.stm != self.attacker {
+                (0, INFINITY)
+            } else {
+                (INFINITY, 0)
+            }
+        } else if board.halfmove_clock >= 100 {
+            (INFINITY, 0)
+        } else {
+            (1, 1)
+        };
+
+        self.nodes[index].proof = proof;
+        self.nodes[index].disproof = disproof;
+    }
+
+    fn select_most_proving(&self) -> usize {
+        let mut index = 0;
+
+        while self.nodes[index].expanded {
+            let node = &self.nodes[index];
+            let or_node = self.is_or_node(index);
+
+            // at an OR node follow the child that is cheapest to prove, at an
+            // AND node the child that is cheapest to disprove
+            index = node
+                .children
+                .iter()
+                .map(|&(_, child)| child)
+                .find(|&child| {
+                    if or_node {
+                        self.nodes[child].proof == node.proof
+                    } else {
+                        self.nodes[child].disproof == node.disproof
+                    }
+                })
+                .expect("Expanded node has no child matching its proof numbers");
+        }
+
+        index
+    }
+
+    fn expand(&mut self, index: usize) {
+        let mut moves = arrayvec::ArrayVec::<Move, 256>::new();
+        legal_moves(&self.nodes[index].board, &mut moves);
+
+        for m in moves {
+            let mut board = self.nodes[index].board;
+            board.make_move(&m);
+
+            let child = self.nodes.len();
+            self.nodes.push(Node::new(board, Some(index)));
+            self.evaluate(child);
+            self.nodes[index].children.push((m, child));
+        }
+
+        self.nodes[index].expanded = true;
+    }
+
+    fn update_ancestors(&mut self, mut index: usize) {
+        loop {
+            let or_node = self.is_or_node(index);
+            let children = self.nodes[index].children.iter().map(|&(_, c)| c);
+
+            let (proof, disproof) = if or_node {
+                (
+                    children.clone().map(|c| self.nodes[c].proof).min().unwrap(),
+                    children.fold(0, |sum: u32, c| sum.saturating_add(self.nodes[c].disproof)),
+                )
+            } else {
+                (
+                    children
+                        .clone()
+                        .fold(0, |sum: u32, c| sum.saturating_add(self.nodes[c].proof)),
+                    children.map(|c| self.nodes[c].disproof).min().unwrap(),
+                )
+            };
+
+            self.nodes[index].proof = proof;
+            self.nodes[index].disproof = disproof;
+
+            match self.nodes[index].parent {
+                Some(parent) => index = parent,
+                None => break,
+            }
+        }
+    }
+
+    fn proof_tree(&self, index: usize) -> ProofTree {
+        let node = &self.nodes[index];
+        let proven = node
+            .children
+            .iter()
+            .filter(|&&(_, child)| self.nodes[child].proof == 0);
+
+        let children = if self.is_or_node(index) {
+            // any proven move will do, prefer the shortest proof
+            proven
+                .map(|&(m, child)| (m, self.proof_tree(child)))
+                .min_by_key(|(_, tree)| tree.depth())
+                .into_iter()
+                .collect()
+        } else {
+            proven
+                .map(|&(m, child)| (m, self.proof_tree(child)))
+                .collect()
+        };
+
+        ProofTree { children }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::{fen, proof_number_search::*};
+
+    // Walk the proof and check that every defence is covered and every leaf is
+    // checkmate
+    fn verify_proof(board: &Board, tree: &ProofTree, attacker: Side) {
+        let mut moves = Vec::new();
+        legal_moves(board, &mut moves);
+
+        if tree.children.is_empty() {
+            assert!(moves.is_empty() && is_in_check(board, board.stm));
+            assert!(board.stm != attacker);
+            return;
+        }
+
+        if board.stm == attacker {
+            assert_eq!(tree.children.len(), 1);
+        } else {
+            assert_eq!(tree.children.len(), moves.len());
+        }
+
+        for (m, child) in &tree.children {
+            let mut board = *board;
+            board.make_move(m);
+            verify_proof(&board, child, attacker);
+        }
+    }
+
+    fn assert_proven(fen: &str) {
+        let board = fen::fen_to_board(fen).unwrap();
+        match pn_search(&board, 1_000_000) {
+            PnResult::Proven(tree) => {
+                verify_proof(&board, &tree, board.stm);
+            }
+            result => panic!("{fen} was not proven: {:?}", result),
+        }
+    }
+
+    #[test]
+    fn proves_mates() {
+        assert_proven("6k1/5ppp/8/8/8/8/8/R5K1 w - - 0 1");
+        assert_proven("kbK5/pp6/1P6/8/8/8/8/R7 w - - 0 1");
+        assert_proven("r1b1kb1r/pppp1ppp/5q2/4n3/3KP3/2N3PN/PPP4P/R1BQ1B1R b kq - 0 1");
+        assert_proven("r6k/6pp/8/6N1/8/1Q6/8/6K1 w - - 0 1");
+    }
+
+    #[test]
+    fn disproves() {
+        // stalemate
+        let board = fen::fen_to_board("k7/8/1Q6/8/8/8/8/7K b - - 0 1").unwrap();
+        assert!(matches!(pn_search(&board, 1000), PnResult::Disproven));
+
+        // the fifty move rule ends the game before a mate can be delivered
+        let board = fen::fen_to_board("8/8/8/3k4/8/3K4/8/7R w - - 98 80").unwrap();
+        assert!(matches!(pn_search(&board, 100_000), PnResult::Disproven));
+    }
+
+    #[test]
+    fn memory_limit() {
+        let board = fen::fen_to_board("8/8/8/3k4/8/3K4/8/7R w - - 0 1").unwrap();
+        assert!(matches!(pn_search(&board, 1000), PnResult::Unknown));
+    }
+}
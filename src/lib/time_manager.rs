@@ -0,0 +1,412 @@
+use crate::chess_move::Move;
+use crate::definitions::*;
+
+use std::time::{Duration, Instant};
+
+/// Default value of the `Move Overhead` option, the time reserved per move for
+/// communication and process scheduling latency.
+pub const DEFAULT_MOVE_OVERHEAD: Duration = Duration::from_millis(10);
+
+/// When no `movestogo` is given, assume the remaining time must last this many
+/// more moves.
+const DEFAULT_MOVES_TO_GO: u32 = 25;
+
+/// The hard limit may use at most this multiple of the soft limit.
+const HARD_LIMIT_RATIO: u32 = 4;
+
+// indexed by the number of consecutive iterations the best move has not changed
+const STABILITY_SCALE: [f64; 5] = [2.2, 1.2, 0.9, 0.8, 0.75];
+
+/// A source of elapsed time since the search started. The search asks the clock
+/// rather than calling `Instant::now()` directly so that time management can be
+/// tested deterministically.
+pub trait Clock {
+    fn elapsed(&self) -> Duration;
+}
+
+pub struct SystemClock {
+    start: Instant,
+}
+
+impl SystemClock {
+    pub fn new() -> SystemClock {
+        SystemClock {
+            start: Instant::now(),
+        }
+    }
+}
+
+impl Default for SystemClock {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl Clock for SystemClock {
+    fn elapsed(&self) -> Duration {
+        self.start.elapsed()
+    }
+}
+
+/// The limits given by a UCI `go` command. Anything not given is `None`.
+#[derive(Clone, Default, Debug, PartialEq)]
+pub struct SearchLimits {
+    pub wtime: Option<Duration>,
+    pub btime: Option<Duration>,
+    pub winc: Option<Duration>,
+    pub binc: Option<Duration>,
+    pub movestogo: Option<u32>,
+    pub movetime: Option<Duration>,
+    pub nodes: Option<u64>,
+    pub depth: Option<u32>,
+    pub mate: Option<u32>,
+    pub infinite: bool,
+}
+
+pub struct TimeManager<C: Clock> {
+    clock: C,
+    limits: SearchLimits,
+
+    // None means there is no time limit, we only stop on other limits or when told to
+    soft_limit: Option<Duration>,
+    hard_limit: Option<Duration>,
+
+    // multiplies the soft limit, adjusted after each completed iteration
+    scale: f64,
+    best_move: Option<Move>,
+    best_move_stability: usize,
+    previous_score: Option<i32>,
+}
+
+impl<C: Clock> TimeManager<C> {
+    pub fn new(limits: SearchLimits, stm: Side, move_overhead: Duration, clock: C) -> Self {
+        let (soft_limit, hard_limit) = compute_limits(&limits, stm, move_overhead);
+
+        TimeManager {
+            clock,
+            limits,
+            soft_limit,
+            hard_limit,
+            scale: 1.0,
+            best_move: None,
+            best_move_stability: 0,
+            previous_score: None,
+        }
+    }
+
+    pub fn elapsed(&self) -> Duration {
+        self.clock.elapsed()
+    }
+
+    pub fn soft_limit(&self) -> Option<Duration> {
+        self.soft_limit
+    }
+
+    pub fn hard_limit(&self) -> Option<Duration> {
+        self.hard_limit
+    }
+
+    /// The soft limit after scaling by the search stability heuristics. This is
+    /// never larger than the hard limit. A fixed `movetime` is used as given.
+    pub fn optimum(&self) -> Option<Duration> {
+        if self.limits.movetime.is_some() {
+            return self.soft_limit;
+        }
+
+        let soft = self.soft_limit?.mul_f64(self.scale);
+        Some(match self.hard_limit {
+            Some(hard) => soft.min(hard),
+            None => soft,
+        })
+    }
+
+    /// Should be polled regularly from inside the search. Returns true if the
+    /// search must be aborted immediately.
+    pub fn stop_search(&self, nodes: u64) -> bool {
+        if self.limits.nodes.is_some_and(|limit| nodes >= limit) {
+            return true;
+        }
+
+        self.hard_limit
+            .is_some_and(|limit| self.clock.elapsed() >= limit)
+    }
+
+    /// Called between iterative deepening iterations. Returns true if another
+    /// iteration should not be started after `depth` has been completed.
+    pub fn stop_iteration(&self, depth: u32) -> bool {
+        if self.limits.depth.is_some_and(|limit| depth >= limit) {
+            return true;
+        }
+
+        // A mate in N needs 2N - 1 plies, after that a full width search has
+        // either found it or proven it does not exist.
+        if self.limits.mate.is_some_and(|n| depth + 1 >= n * 2) {
+            return true;
+        }
+
+        self.optimum()
+            .is_some_and(|optimum| self.clock.elapsed() >= optimum)
+    }
+
+    /// Update the time scaling after completing an iteration. We extend the
+    /// search when the best move keeps changing or the score is dropping, and
+    /// shorten it when most of the nodes were spent proving the best move.
+    pub fn on_iteration_complete(
+        &mut self,
+        best_move: Move,
+        score: i32,
+        best_move_nodes: u64,
+        total_nodes: u64,
+    ) {
+        if self.best_move == Some(best_move) {
+            self.best_move_stability =
+                (self.best_move_stability + 1).min(STABILITY_SCALE.len() - 1);
+        } else {
+            self.best_move_stability = 0;
+        }
+
+        let stability_scale = STABILITY_SCALE[self.best_move_stability];
+
+        // a score drop of 100cp or more extends the search by 50%
+        let score_scale = match self.previous_score {
+            Some(previous) => 1.0 + (previous - score).clamp(0, 100) as f64 / 200.0,
+            None => 1.0,
+        };
+
+        let node_scale = if total_nodes > 0 {
+            let fraction = best_move_nodes as f64 / total_nodes as f64;
+            (1.5 - fraction) * 1.35
+        } else {
+            1.0
+        };
+
+        self.best_move = Some(best_move);
+        self.previous_score = Some(score);
+        self.scale = stability_scale * score_scale * node_scale;
+    }
+}
+
+fn compute_limits(
+    limits: &SearchLimits,
+    stm: Side,
+    move_overhead: Duration,
+) -> (Option<Duration>, Option<Duration>) {
+    if limits.infinite {
+        return (None, None);
+    }
+
+    if let Some(movetime) = limits.movetime {
+        let time = movetime.saturating_sub(move_overhead);
+        return (Some(time), Some(time));
+    }
+
+    let (time, inc) = match stm {
+        Side::White => (limits.wtime, limits.winc),
+        Side::Black => (limits.btime, limits.binc),
+    };
+
+    let time = match time {
+        Some(time) => time,
+        None => return (None, None),
+    };
+    let inc = inc.unwrap_or(Duration::ZERO);
+
+    let available = time.saturating_sub(move_overhead);
+    let moves_to_go = limits.movestogo.unwrap_or(DEFAULT_MOVES_TO_GO).max(1);
+
+    // never plan on using more than 3/4 of the remaining time on one move
+    let maximum = available * 3 / 4;
+    let soft = (available / moves_to_go + inc * 3 / 4).min(maximum);
+    let hard = (soft * HARD_LIMIT_RATIO).min(maximum);
+
+    (Some(soft), Some(hard))
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::{chess_move::MoveFlag, time_manager::*};
+
+    use std::cell::Cell;
+
+    struct ManualClock<'a>(&'a Cell<Duration>);
+
+    impl<'a> Clock for ManualClock<'a> {
+        fn elapsed(&self) -> Duration {
+            self.0.get()
+        }
+    }
+
+    fn ms(x: u64) -> Duration {
+        Duration::from_millis(x)
+    }
+
+    const E2E4: Move = Move::new(Square::E2, Square::E4, MoveFlag::PawnDoubleMove);
+
+    const D2D4: Move = Move::new(Square::D2, Square::D4, MoveFlag::PawnDoubleMove);
+
+    #[test]
+    fn infinite_and_depth_limits() {
+        let time = Cell::new(ms(0));
+        let limits = SearchLimits {
+            infinite: true,
+            wtime: Some(ms(1000)),
+            ..Default::default()
+        };
+        let tm = TimeManager::new(limits, Side::White, ms(0), ManualClock(&time));
+        time.set(ms(1_000_000));
+        assert!(!tm.stop_search(u64::MAX));
+        assert!(!tm.stop_iteration(100));
+
+        let limits = SearchLimits {
+            depth: Some(5),
+            ..Default::default()
+        };
+        let tm = TimeManager::new(limits, Side::White, ms(0), ManualClock(&time));
+        assert!(!tm.stop_iteration(4));
+        assert!(tm.stop_iteration(5));
+
+        let limits = SearchLimits {
+            mate: Some(3),
+            ..Default::default()
+        };
+        let tm = TimeManager::new(limits, Side::White, ms(0), ManualClock(&time));
+        assert!(!tm.stop_iteration(4));
+        assert!(tm.stop_iteration(5));
+    }
+
+    #[test]
+    fn movetime_and_nodes() {
+        let time = Cell::new(ms(0));
+        let limits = SearchLimits {
+            movetime: Some(ms(500)),
+            nodes: Some(1000),
+            ..Default::default()
+        };
+        let tm = TimeManager::new(limits, Side::Black, ms(20), ManualClock(&time));
+        assert_eq!(tm.hard_limit(), Some(ms(480)));
+        assert!(!tm.stop_search(999));
+        assert!(tm.stop_search(1000));
+
+        time.set(ms(479));
+        assert!(!tm.stop_search(0));
+        time.set(ms(480));
+        assert!(tm.stop_search(0));
+    }
+
+    #[test]
+    fn movetime_is_not_scaled() {
+        let time = Cell::new(ms(0));
+        let limits = SearchLimits {
+            movetime: Some(ms(500)),
+            ..Default::default()
+        };
+        let mut tm = TimeManager::new(limits, Side::White, ms(0), ManualClock(&time));
+        assert_eq!(tm.soft_limit(), Some(ms(500)));
+        assert_eq!(tm.hard_limit(), Some(ms(500)));
+
+        // a stable best move would otherwise shorten the search
+        for _ in 0..5 {
+            tm.on_iteration_complete(E2E4, 20, 950, 1000);
+        }
+        assert_eq!(tm.optimum(), Some(ms(500)));
+
+        time.set(ms(499));
+        assert!(!tm.stop_iteration(1));
+        time.set(ms(500));
+        assert!(tm.stop_iteration(1));
+    }
+
+    #[test]
+    fn clock_limits_use_side_to_move() {
+        let time = Cell::new(ms(0));
+        let limits = SearchLimits {
+            wtime: Some(ms(60_010)),
+            btime: Some(ms(1_010)),
+            winc: Some(ms(1000)),
+            movestogo: Some(20),
+            ..Default::default()
+        };
+
+        let tm = TimeManager::new(limits.clone(), Side::White, ms(10), ManualClock(&time));
+        assert_eq!(tm.soft_limit(), Some(ms(3750)));
+        assert_eq!(tm.hard_limit(), Some(ms(15000)));
+
+        let tm = TimeManager::new(limits, Side::Black, ms(10), ManualClock(&time));
+        assert_eq!(tm.soft_limit(), Some(ms(50)));
+        assert_eq!(tm.hard_limit(), Some(ms(200)));
+    }
+
+    #[test]
+    fn move_overhead_is_respected() {
+        let time = Cell::new(ms(0));
+        let limits = SearchLimits {
+            wtime: Some(ms(100)),
+            movestogo: Some(1),
+            ..Default::default()
+        };
+        let tm = TimeManager::new(limits, Side::White, ms(200), ManualClock(&time));
+        assert_eq!(tm.hard_limit(), Some(ms(0)));
+        assert!(tm.stop_search(0));
+    }
+
+    fn manager(time: &Cell<Duration>) -> TimeManager<ManualClock<'_>> {
+        let limits = SearchLimits {
+            wtime: Some(ms(100_000)),
+            movestogo: Some(100),
+            ..Default::default()
+        };
+        TimeManager::new(limits, Side::White, ms(0), ManualClock(time))
+    }
+
+    #[test]
+    fn stability_scaling() {
+        let time = Cell::new(ms(0));
+        let mut tm = manager(&time);
+        assert_eq!(tm.optimum(), Some(ms(1000)));
+
+        // one move dominating the node count and staying stable shortens the search
+        for _ in 0..5 {
+            tm.on_iteration_complete(E2E4, 20, 950, 1000);
+        }
+        let stable = tm.optimum().unwrap();
+        assert!(stable < ms(1000));
+
+        time.set(stable - ms(1));
+        assert!(!tm.stop_iteration(1));
+        time.set(stable);
+        assert!(tm.stop_iteration(1));
+
+        // a change of best move extends it
+        tm.on_iteration_complete(D2D4, 20, 950, 1000);
+        assert!(tm.optimum().unwrap() > stable);
+    }
+
+    #[test]
+    fn score_drop_scaling() {
+        let time = Cell::new(ms(0));
+        let mut steady = manager(&time);
+        let mut dropping = manager(&time);
+
+        steady.on_iteration_complete(E2E4, 20, 500, 1000);
+        steady.on_iteration_complete(E2E4, 20, 500, 1000);
+        dropping.on_iteration_complete(E2E4, 20, 500, 1000);
+        dropping.on_iteration_complete(E2E4, -80, 500, 1000);
+
+        assert!(dropping.optimum() > steady.optimum());
+    }
+
+    #[test]
+    fn optimum_never_exceeds_hard_limit() {
+        let time = Cell::new(ms(0));
+        let limits = SearchLimits {
+            btime: Some(ms(10_000)),
+            ..Default::default()
+        };
+        let mut tm = TimeManager::new(limits, Side::Black, ms(0), ManualClock(&time));
+        for i in 0..10 {
+            let m = if i % 2 == 0 { E2E4 } else { D2D4 };
+            tm.on_iteration_complete(m, -100 * i, 0, 1000);
+            assert!(tm.optimum() <= tm.hard_limit());
+        }
+    }
+}
@@ -0,0 +1,296 @@
+use crate::definitions::*;
+use crate::evaluation::{self, Score};
+use crate::pawns;
+
+use strum::{EnumCount, EnumIter, IntoEnumIterator};
+
+/// The groups of evaluation parameters shown in an evaluation trace.
+#[derive(EnumIter, EnumCount, Copy, Clone, PartialEq, Debug)]
+pub enum Term {
+    Material,
+    Pawns,
+    PassedPawns,
+    Mobility,
+    Pieces,
+    KingSafety,
+    Threats,
+}
+
+impl Term {
+    pub const fn name(&self) -> &'static str {
+        match self {
+            Term::Material => "Material",
+            Term::Pawns => "Pawns",
+            Term::PassedPawns => "Passed pawns",
+            Term::Mobility => "Mobility",
+            Term::Pieces => "Pieces",
+            Term::KingSafety => "King safety",
+            Term::Threats => "Threats",
+        }
+    }
+}
+
+/// Every table of parameters used by the evaluation. A single value counts as
+/// a table of length one.
+#[derive(EnumIter, EnumCount, Copy, Clone, PartialEq, Debug)]
+pub enum Param {
+    PieceValues,
+    PassedPawn,
+    CandidatePasser,
+    ConnectedPawn,
+    PhalanxPawn,
+    IsolatedPawn,
+    DoubledPawn,
+    BackwardPawn,
+    PassedPawnBlocked,
+    PassedPawnOurKingDistance,
+    PassedPawnTheirKingDistance,
+    UnstoppablePasser,
+    KnightMobility,
+    BishopMobility,
+    RookMobility,
+    QueenMobility,
+    RookOpenFile,
+    RookSemiOpenFile,
+    RookOnSeventh,
+    BishopPair,
+    KnightOutpost,
+    BishopOutpost,
+    KingAttackerWeight,
+    KingZoneAttack,
+    SafeCheck,
+    PawnShelter,
+    PawnStorm,
+    ThreatByPawn,
+    ThreatByMinor,
+    HangingPiece,
+}
+
+impl Param {
+    /// The current values of the parameter.
+    pub fn values(&self) -> &'static [Score] {
+        match self {
+            Param::PieceValues => &evaluation::PIECE_VALUES,
+            Param::PassedPawn => &pawns::PASSED_PAWN,
+            Param::CandidatePasser => &pawns::CANDIDATE_PASSER,
+            Param::ConnectedPawn => &pawns::CONNECTED_PAWN,
+            Param::PhalanxPawn => &pawns::PHALANX_PAWN,
+            Param::IsolatedPawn => std::slice::from_ref(&pawns::ISOLATED_PAWN),
+            Param::DoubledPawn => std::slice::from_ref(&pawns::DOUBLED_PAWN),
+            Param::BackwardPawn => std::slice::from_ref(&pawns::BACKWARD_PAWN),
+            Param::PassedPawnBlocked => &pawns::PASSED_PAWN_BLOCKED,
+            Param::PassedPawnOurKingDistance => {
+                std::slice::from_ref(&pawns::PASSED_PAWN_OUR_KING_DISTANCE)
+            }
+            Param::PassedPawnTheirKingDistance => {
+                std::slice::from_ref(&pawns::PASSED_PAWN_THEIR_KING_DISTANCE)
+            }
+            Param::UnstoppablePasser => std::slice::from_ref(&pawns::UNSTOPPABLE_PASSER),
+            Param::KnightMobility => &evaluation::KNIGHT_MOBILITY,
+            Param::BishopMobility => &evaluation::BISHOP_MOBILITY,
+            Param::RookMobility => &evaluation::ROOK_MOBILITY,
+            Param::QueenMobility => &evaluation::QUEEN_MOBILITY,
+            Param::RookOpenFile => std::slice::from_ref(&evaluation::ROOK_OPEN_FILE),
+            Param::RookSemiOpenFile => std::slice::from_ref(&evaluation::ROOK_SEMI_OPEN_FILE),
+            Param::RookOnSeventh => std::slice::from_ref(&evaluation::ROOK_ON_SEVENTH),
+            Param::BishopPair => std::slice::from_ref(&evaluation::BISHOP_PAIR),
+            Param::KnightOutpost => std::slice::from_ref(&evaluation::KNIGHT_OUTPOST),
+            Param::BishopOutpost => std::slice::from_ref(&evaluation::BISHOP_OUTPOST),
+            Param::KingAttackerWeight => &evaluation::KING_ATTACKER_WEIGHT,
+            Param::KingZoneAttack => std::slice::from_ref(&evaluation::KING_ZONE_ATTACK),
+            Param::SafeCheck => &evaluation::SAFE_CHECK,
+            Param::PawnShelter => &evaluation::PAWN_SHELTER,
+            Param::PawnStorm => &evaluation::PAWN_STORM,
+            Param::ThreatByPawn => &evaluation::THREAT_BY_PAWN,
+            Param::ThreatByMinor => &evaluation::THREAT_BY_MINOR,
+            Param::HangingPiece => std::slice::from_ref(&evaluation::HANGING_PIECE),
+        }
+    }
+
+    /// The name of the constant holding the parameter.
+    pub const fn name(&self) -> &'static str {
+        match self {
+            Param::PieceValues => "PIECE_VALUES",
+            Param::PassedPawn => "PASSED_PAWN",
+            Param::CandidatePasser => "CANDIDATE_PASSER",
+            Param::ConnectedPawn => "CONNECTED_PAWN",
+            Param::PhalanxPawn => "PHALANX_PAWN",
+            Param::IsolatedPawn => "ISOLATED_PAWN",
+            Param::DoubledPawn => "DOUBLED_PAWN",
+            Param::BackwardPawn => "BACKWARD_PAWN",
+            Param::PassedPawnBlocked => "PASSED_PAWN_BLOCKED",
+            Param::PassedPawnOurKingDistance => "PASSED_PAWN_OUR_KING_DISTANCE",
+            Param::PassedPawnTheirKingDistance => "PASSED_PAWN_THEIR_KING_DISTANCE",
+            Param::UnstoppablePasser => "UNSTOPPABLE_PASSER",
+            Param::KnightMobility => "KNIGHT_MOBILITY",
+            Param::BishopMobility => "BISHOP_MOBILITY",
+            Param::RookMobility => "ROOK_MOBILITY",
+            Param::QueenMobility => "QUEEN_MOBILITY",
+            Param::RookOpenFile => "ROOK_OPEN_FILE",
+            Param::RookSemiOpenFile => "ROOK_SEMI_OPEN_FILE",
+            Param::RookOnSeventh => "ROOK_ON_SEVENTH",
+            Param::BishopPair => "BISHOP_PAIR",
+            Param::KnightOutpost => "KNIGHT_OUTPOST",
+            Param::BishopOutpost => "BISHOP_OUTPOST",
+            Param::KingAttackerWeight => "KING_ATTACKER_WEIGHT",
+            Param::KingZoneAttack => "KING_ZONE_ATTACK",
+            Param::SafeCheck => "SAFE_CHECK",
+            Param::PawnShelter => "PAWN_SHELTER",
+            Param::PawnStorm => "PAWN_STORM",
+            Param::ThreatByPawn => "THREAT_BY_PAWN",
+            Param::ThreatByMinor => "THREAT_BY_MINOR",
+            Param::HangingPiece => "HANGING_PIECE",
+        }
+    }
+
+    pub const fn term(&self) -> Term {
+        match self {
+            Param::PieceValues => Term::Material,
+            Param::PassedPawn
+            | Param::CandidatePasser
+            | Param::ConnectedPawn
+            | Param::PhalanxPawn
+            | Param::IsolatedPawn
+            | Param::DoubledPawn
+            | Param::BackwardPawn => Term::Pawns,
+            Param::PassedPawnBlocked
+            | Param::PassedPawnOurKingDistance
+            | Param::PassedPawnTheirKingDistance
+            | Param::UnstoppablePasser => Term::PassedPawns,
+            Param::KnightMobility
+            | Param::BishopMobility
+            | Param::RookMobility
+            | Param::QueenMobility => Term::Mobility,
+            Param::RookOpenFile
+            | Param::RookSemiOpenFile
+            | Param::RookOnSeventh
+            | Param::BishopPair
+            | Param::KnightOutpost
+            | Param::BishopOutpost => Term::Pieces,
+            Param::KingAttackerWeight
+            | Param::KingZoneAttack
+            | Param::SafeCheck
+            | Param::PawnShelter
+            | Param::PawnStorm => Term::KingSafety,
+            Param::ThreatByPawn | Param::ThreatByMinor | Param::HangingPiece => Term::Threats,
+        }
+    }
+}
+
+/// Receives every parameter the evaluation applies, so that the score can be
+/// broken down afterwards. The evaluation functions are generic over this, and
+/// `NoTrace` compiles away entirely.
+pub trait Trace {
+    /// `side` gained `count` times the value of the parameter at `index`.
+    fn add(&mut self, param: Param, index: usize, side: Side, count: i32);
+}
+
+pub struct NoTrace;
+
+impl Trace for NoTrace {
+    #[inline(always)]
+    fn add(&mut self, _: Param, _: usize, _: Side, _: i32) {}
+}
+
+/// A breakdown of the evaluation of a position, as returned by
+/// `evaluation::eval_trace`.
+#[derive(Clone, Debug, PartialEq)]
+pub struct EvalTrace {
+    /// The score of each term for each side, before scaling and tapering.
+    pub terms: [[Score; Side::COUNT]; Term::COUNT],
+    pub phase: i32,
+    /// The endgame scale factor, out of `endgame::SCALE_NORMAL`.
+    pub scale: i32,
+    /// Set if a specialised endgame evaluator or a dead draw replaced the
+    /// general evaluation.
+    pub endgame: Option<i32>,
+    /// The final evaluation, from white's point of view.
+    pub eval: i32,
+}
+
+impl EvalTrace {
+    pub fn new() -> EvalTrace {
+        EvalTrace {
+            terms: [[Score::default(); Side::COUNT]; Term::COUNT],
+            phase: 0,
+            scale: 0,
+            endgame: None,
+            eval: 0,
+        }
+    }
+
+    /// The sum of all terms, from white's point of view.
+    pub fn total(&self) -> Score {
+        self.terms.iter().fold(Score::default(), |total, term| {
+            total + term[Side::White as usize] - term[Side::Black as usize]
+        })
+    }
+}
+
+impl Default for EvalTrace {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl Trace for EvalTrace {
+    fn add(&mut self, param: Param, index: usize, side: Side, count: i32) {
+        self.terms[param.term() as usize][side as usize] += param.values()[index] * count;
+    }
+}
+
+impl std::fmt::Display for EvalTrace {
+    fn fmt(&self, f: &mut std::fmt::Formatter) -> std::fmt::Result {
+        let row = |f: &mut std::fmt::Formatter, name: &str, white: Score, black: Score| {
+            let total = white - black;
+            writeln!(
+                f,
+                "{:>12} | {:>6} {:>6} | {:>6} {:>6} | {:>6} {:>6}",
+                name,
+                white.mg(),
+                white.eg(),
+                black.mg(),
+                black.eg(),
+                total.mg(),
+                total.eg()
+            )
+        };
+        let separator = "-------------+---------------+---------------+--------------";
+
+        writeln!(
+            f,
+            "{:>12} | {:>13} | {:>13} | {:>13}",
+            "Term", "White", "Black", "Total"
+        )?;
+        writeln!(
+            f,
+            "{:>12} | {:>6} {:>6} | {:>6} {:>6} | {:>6} {:>6}",
+            "", "mg", "eg", "mg", "eg", "mg", "eg"
+        )?;
+        writeln!(f, "{separator}")?;
+
+        let mut white_total = Score::default();
+        let mut black_total = Score::default();
+        for term in Term::iter() {
+            let [white, black] = self.terms[term as usize];
+            white_total += white;
+            black_total += black;
+            row(f, term.name(), white, black)?;
+        }
+        writeln!(f, "{separator}")?;
+        row(f, "Total", white_total, black_total)?;
+        writeln!(f)?;
+
+        writeln!(f, "Phase: {}/{}", self.phase, evaluation::MAX_PHASE)?;
+        writeln!(
+            f,
+            "Endgame scale: {}/{}",
+            self.scale,
+            crate::endgame::SCALE_NORMAL
+        )?;
+        if let Some(eval) = self.endgame {
+            writeln!(f, "Endgame evaluation: {eval}")?;
+        }
+        write!(f, "Final evaluation: {} (white side)", self.eval)
+    }
+}
@@ -0,0 +1,464 @@
+use crate::board::Board;
+use crate::definitions::*;
+use crate::fen;
+
+use std::io::{BufRead, Read, Write};
+use strum::IntoEnumIterator;
+
+/// The size of a packed position, in bytes.
+///
+/// | bytes  | contents                                                  |
+/// |--------|-----------------------------------------------------------|
+/// | 0..8   | occupancy bitboard                                        |
+/// | 8..24  | a 4-bit piece code for each occupied square, in order     |
+/// | 24     | castling rights (bits 0-3, KQkq), black to move (bit 7)   |
+/// | 25     | en passant square, 64 if there is none                    |
+/// | 26     | halfmove clock                                            |
+/// | 27..29 | fullmove number                                           |
+/// | 29..31 | score in centipawns, from white's point of view           |
+/// | 31     | game result, 0 for a black win, 1 a draw, 2 a white win   |
+///
+/// Multi-byte fields are little endian.
+pub const PACKED_SIZE: usize = 32;
+
+const MAX_PIECES: u32 = 32;
+const NO_EN_PASSANT: u8 = 64;
+const BLACK_TO_MOVE: u8 = 0x80;
+
+#[derive(Debug)]
+pub enum DataError {
+    Io(std::io::Error),
+    /// The position has more pieces or larger move counters than the packed
+    /// format can hold.
+    Unpackable(String),
+    /// A packed record or text line is malformed.
+    Invalid(String),
+}
+
+impl std::fmt::Display for DataError {
+    fn fmt(&self, f: &mut std::fmt::Formatter) -> std::fmt::Result {
+        match self {
+            DataError::Io(e) => write!(f, "Could not access training data: {e}"),
+            DataError::Unpackable(s) => write!(f, "Cannot pack position: {s}"),
+            DataError::Invalid(s) => write!(f, "Invalid training data: {s}"),
+        }
+    }
+}
+
+impl std::error::Error for DataError {}
+
+impl From<std::io::Error> for DataError {
+    fn from(e: std::io::Error) -> Self {
+        DataError::Io(e)
+    }
+}
+
+#[derive(Copy, Clone, PartialEq, Debug)]
+pub enum GameResult {
+    BlackWin,
+    Draw,
+    WhiteWin,
+}
+
+impl GameResult {
+    /// The result from white's point of view, 1 for a win, 0.5 for a draw and
+    /// 0 for a loss.
+    pub const fn to_f64(self) -> f64 {
+        match self {
+            GameResult::BlackWin => 0.0,
+            GameResult::Draw => 0.5,
+            GameResult::WhiteWin => 1.0,
+        }
+    }
+
+    fn parse(s: &str) -> Option<GameResult> {
+        match s {
+            "0.0" | "0" | "0-1" => Some(GameResult::BlackWin),
+            "0.5" | "1/2-1/2" => Some(GameResult::Draw),
+            "1.0" | "1" | "1-0" => Some(GameResult::WhiteWin),
+            _ => None,
+        }
+    }
+}
+
+/// A position labelled with a search score and the result of the game it was
+/// played in.
+#[derive(Clone, PartialEq)]
+pub struct TrainingEntry {
+    pub board: Board,
+    /// The score in centipawns, from white's point of view.
+    pub score: i16,
+    pub result: GameResult,
+}
+
+impl TrainingEntry {
+    /// Parse the text format, `<fen> | <score> | <result>`, with the score in
+    /// centipawns and the result as 1.0, 0.5 or 0.0, both from white's point
+    /// of view.
+    pub fn from_text(line: &str) -> Result<TrainingEntry, DataError> {
+        let fields: Vec<&str> = line.split('|').map(str::trim).collect();
+        let [fen, score, result] = fields[..] else {
+            return Err(DataError::Invalid(format!("Expected 3 fields in {line}")));
+        };
+
+        let board = fen::fen_to_board(fen).map_err(DataError::Invalid)?;
+        if !board.has_one_king_each() {
+            return Err(DataError::Invalid(format!("Missing or extra king in {line}")));
+        }
+        let score = score
+            .parse()
+            .map_err(|_| DataError::Invalid(format!("Invalid score in {line}")))?;
+        let result = GameResult::parse(result)
+            .ok_or_else(|| DataError::Invalid(format!("Invalid result in {line}")))?;
+
+        Ok(TrainingEntry {
+            board,
+            score,
+            result,
+        })
+    }
+
+    pub fn to_text(&self) -> String {
+        format!(
+            "{} | {} | {:.1}",
+            fen::board_to_fen(&self.board),
+            self.score,
+            self.result.to_f64()
+        )
+    }
+}
+
+#[derive(Copy, Clone, PartialEq, Debug)]
+pub struct PackedBoard(pub [u8; PACKED_SIZE]);
+
+impl PackedBoard {
+    pub fn pack(entry: &TrainingEntry) -> Result<PackedBoard, DataError> {
+        let board = &entry.board;
+        let occupied = board.occupied_squares();
+        if occupied.popcnt() > MAX_PIECES {
+            return Err(DataError::Unpackable(format!(
+                "{} pieces on the board",
+                occupied.popcnt()
+            )));
+        }
+        let halfmove_clock = u8::try_from(board.halfmove_clock).map_err(|_| {
+            DataError::Unpackable(format!("halfmove clock {}", board.halfmove_clock))
+        })?;
+        let fullmove_number = u16::try_from(board.fullmove_number).map_err(|_| {
+            DataError::Unpackable(format!("fullmove number {}", board.fullmove_number))
+        })?;
+
+        let mut bytes = [0; PACKED_SIZE];
+        bytes[0..8].copy_from_slice(&occupied.0.to_le_bytes());
+
+        let mut squares = occupied;
+        let mut i = 0;
+        while squares != BB_EMPTY {
+            let piece = board.get_square(squares.poplsb()).unwrap();
+            bytes[8 + i / 2] |= (piece as u8) << (4 * (i % 2));
+            i += 1;
+        }
+
+        let castling = [
+            board.white_king_castle,
+            board.white_queen_castle,
+            board.black_king_castle,
+            board.black_queen_castle,
+        ];
+        for (bit, &right) in castling.iter().enumerate() {
+            bytes[24] |= (right as u8) << bit;
+        }
+        if board.stm == Side::Black {
+            bytes[24] |= BLACK_TO_MOVE;
+        }
+
+        bytes[25] = board.en_passant.map_or(NO_EN_PASSANT, |sq| sq as u8);
+        bytes[26] = halfmove_clock;
+        bytes[27..29].copy_from_slice(&fullmove_number.to_le_bytes());
+        bytes[29..31].copy_from_slice(&entry.score.to_le_bytes());
+        bytes[31] = entry.result as u8;
+
+        Ok(PackedBoard(bytes))
+    }
+
+    pub fn unpack(&self) -> Result<TrainingEntry, DataError> {
+        let bytes = &self.0;
+        let mut board = Board::new();
+
+        let mut squares = BB(u64::from_le_bytes(bytes[0..8].try_into().unwrap()));
+        if squares.popcnt() > MAX_PIECES {
+            return Err(DataError::Invalid(format!(
+                "{} pieces on the board",
+                squares.popcnt()
+            )));
+        }
+        let mut i = 0;
+        while squares != BB_EMPTY {
+            let code = (bytes[8 + i / 2] >> (4 * (i % 2))) & 0xf;
+            let piece = Piece::iter()
+                .nth(code as usize)
+                .ok_or_else(|| DataError::Invalid(format!("Invalid piece code {code}")))?;
+            board.set_square(squares.poplsb(), piece);
+            i += 1;
+        }
+        if !board.has_one_king_each() {
+            return Err(DataError::Invalid("Missing or extra king".to_string()));
+        }
+
+        board.white_king_castle = bytes[24] & 1 != 0;
+        board.white_queen_castle = bytes[24] & 2 != 0;
+        board.black_king_castle = bytes[24] & 4 != 0;
+        board.black_queen_castle = bytes[24] & 8 != 0;
+        board.stm = if bytes[24] & BLACK_TO_MOVE != 0 {
+            Side::Black
+        } else {
+            Side::White
+        };
+
+        board.en_passant = match bytes[25] {
+            NO_EN_PASSANT => None,
+            sq => Some(Square::safe_from_index(sq as usize).map_err(DataError::Invalid)?),
+        };
+        board.halfmove_clock = bytes[26] as u32;
+        board.fullmove_number = u16::from_le_bytes([bytes[27], bytes[28]]) as u32;
+
+        let score = i16::from_le_bytes([bytes[29], bytes[30]]);
+        let result = match bytes[31] {
+            0 => GameResult::BlackWin,
+            1 => GameResult::Draw,
+            2 => GameResult::WhiteWin,
+            r => return Err(DataError::Invalid(format!("Invalid result {r}"))),
+        };
+
+        Ok(TrainingEntry {
+            board,
+            score,
+            result,
+        })
+    }
+}
+
+/// Reads packed positions until the end of the input.
+pub struct PackedReader<R: Read> {
+    reader: R,
+}
+
+impl<R: Read> PackedReader<R> {
+    pub fn new(reader: R) -> PackedReader<R> {
+        PackedReader { reader }
+    }
+}
+
+impl<R: Read> Iterator for PackedReader<R> {
+    type Item = Result<TrainingEntry, DataError>;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        let mut bytes = [0; PACKED_SIZE];
+        let mut read = 0;
+        while read < PACKED_SIZE {
+            match self.reader.read(&mut bytes[read..]) {
+                Ok(0) => break,
+                Ok(n) => read += n,
+                Err(e) if e.kind() == std::io::ErrorKind::Interrupted => (),
+                Err(e) => return Some(Err(e.into())),
+            }
+        }
+
+        match read {
+            0 => None,
+            PACKED_SIZE => Some(PackedBoard(bytes).unpack()),
+            _ => Some(Err(DataError::Invalid(format!(
+                "Truncated record of {read} bytes"
+            )))),
+        }
+    }
+}
+
+pub struct PackedWriter<W: Write> {
+    writer: W,
+}
+
+impl<W: Write> PackedWriter<W> {
+    pub fn new(writer: W) -> PackedWriter<W> {
+        PackedWriter { writer }
+    }
+
+    pub fn write(&mut self, entry: &TrainingEntry) -> Result<(), DataError> {
+        self.writer.write_all(&PackedBoard::pack(entry)?.0)?;
+        Ok(())
+    }
+
+    pub fn flush(&mut self) -> Result<(), DataError> {
+        self.writer.flush()?;
+        Ok(())
+    }
+}
+
+/// Convert the text format to packed positions, returning the number of
+/// positions converted. Empty lines are skipped.
+pub fn text_to_packed<R: BufRead, W: Write>(input: R, output: W) -> Result<usize, DataError> {
+    let mut writer = PackedWriter::new(output);
+    let mut count = 0;
+    for line in input.lines() {
+        let line = line?;
+        if line.trim().is_empty() {
+            continue;
+        }
+        writer.write(&TrainingEntry::from_text(&line)?)?;
+        count += 1;
+    }
+    writer.flush()?;
+    Ok(count)
+}
+
+/// Convert packed positions to the text format, returning the number of
+/// positions converted.
+pub fn packed_to_text<R: Read, W: Write>(input: R, mut output: W) -> Result<usize, DataError> {
+    let mut count = 0;
+    for entry in PackedReader::new(input) {
+        writeln!(output, "{}", entry?.to_text())?;
+        count += 1;
+    }
+    output.flush()?;
+    Ok(count)
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::chess_move::Move;
+    use crate::generate_moves::legal_moves;
+    use crate::training_data::*;
+
+    const POSITIONS: [&str; 6] = [
+        "rnbqkbnr/pppppppp/8/8/8/8/PPPPPPPP/RNBQKBNR w KQkq - 0 1",
+        "r3k2r/p1ppqpb1/bn2pnp1/3PN3/1p2P3/2N2Q1p/PPPBBPPP/R3K2R w KQkq - 0 10",
+        "r3k2r/p1ppqpb1/bn2pnp1/3PN3/Pp2P3/2N2Q1p/1PPBBPPP/R3K2R b KQkq a3 0 1",
+        "4rrk1/pp1n3p/3q2pQ/2p1pb2/2PP4/2P3N1/P2B2PP/4RRK1 b Kq - 7 19",
+        "5rk1/q6p/2p3bR/1pPp1rP1/1P1Pp3/P3B1Q1/1K3P2/R7 w - - 93 90",
+        "8/8/8/8/8/8/8/K1k5 b - - 255 65535",
+    ];
+
+    fn entry(board: Board, score: i16, result: GameResult) -> TrainingEntry {
+        TrainingEntry {
+            board,
+            score,
+            result,
+        }
+    }
+
+    fn assert_round_trip(entry: &TrainingEntry) {
+        let packed = PackedBoard::pack(entry).unwrap();
+        let unpacked = packed.unpack().unwrap();
+        assert_eq!(
+            fen::board_to_fen(&entry.board),
+            fen::board_to_fen(&unpacked.board)
+        );
+        assert!(entry.board == unpacked.board);
+        assert_eq!(entry.score, unpacked.score);
+        assert_eq!(entry.result, unpacked.result);
+    }
+
+    #[test]
+    fn round_trip() {
+        for (i, fen) in POSITIONS.iter().enumerate() {
+            let board = fen::fen_to_board(fen).unwrap();
+            let result = [GameResult::BlackWin, GameResult::Draw, GameResult::WhiteWin][i % 3];
+            assert_round_trip(&entry(board, -300 + 137 * i as i16, result));
+        }
+    }
+
+    #[test]
+    fn round_trip_games() {
+        // play pseudo random games, so that captures, promotions, castling and
+        // en passant squares all show up
+        let mut seed = 0x2545_f491_4f6c_dd1d_u64;
+        for _ in 0..20 {
+            let mut board = fen::fen_to_board(POSITIONS[0]).unwrap();
+            for ply in 0..200 {
+                assert_round_trip(&entry(board, ply * 3 - 300, GameResult::Draw));
+
+                let mut moves = Vec::<Move>::new();
+                legal_moves(&board, &mut moves);
+                if moves.is_empty() {
+                    break;
+                }
+                seed ^= seed << 13;
+                seed ^= seed >> 7;
+                seed ^= seed << 17;
+                board.make_move(&moves[seed as usize % moves.len()]);
+            }
+        }
+    }
+
+    #[test]
+    fn unpackable() {
+        let mut board = fen::fen_to_board(POSITIONS[0]).unwrap();
+        board.halfmove_clock = 256;
+        assert!(matches!(
+            PackedBoard::pack(&entry(board, 0, GameResult::Draw)),
+            Err(DataError::Unpackable(_))
+        ));
+
+        let board =
+            fen::fen_to_board("qqqqqqqq/qqqqqqqq/qqqqqqqq/qqqqqqqq/qqqqqqqq/8/8/K6k w - - 0 1")
+                .unwrap();
+        assert!(matches!(
+            PackedBoard::pack(&entry(board, 0, GameResult::Draw)),
+            Err(DataError::Unpackable(_))
+        ));
+    }
+
+    #[test]
+    fn invalid_records() {
+        let board = fen::fen_to_board(POSITIONS[0]).unwrap();
+        let packed = PackedBoard::pack(&entry(board, 0, GameResult::Draw)).unwrap();
+
+        let mut bad = packed;
+        bad.0[8] = 0xff;
+        assert!(matches!(bad.unpack(), Err(DataError::Invalid(_))));
+
+        let mut bad = packed;
+        bad.0[31] = 3;
+        assert!(matches!(bad.unpack(), Err(DataError::Invalid(_))));
+
+        let mut reader = PackedReader::new(&packed.0[..20]);
+        assert!(matches!(reader.next(), Some(Err(DataError::Invalid(_)))));
+
+        let kingless = fen::fen_to_board("8/8/8/8/8/8/8/K7 w - - 0 1").unwrap();
+        let packed = PackedBoard::pack(&entry(kingless, 0, GameResult::Draw)).unwrap();
+        assert!(matches!(packed.unpack(), Err(DataError::Invalid(_))));
+        assert!(matches!(
+            TrainingEntry::from_text("8/8/8/8/8/8/8/K7 w - - 0 1 | 0 | 0.5"),
+            Err(DataError::Invalid(_))
+        ));
+    }
+
+    #[test]
+    fn text_conversion() {
+        let text = "\
+rnbqkbnr/pppppppp/8/8/8/8/PPPPPPPP/RNBQKBNR w KQkq - 0 1 | 25 | 0.5
+r3k2r/p1ppqpb1/bn2pnp1/3PN3/Pp2P3/2N2Q1p/1PPBBPPP/R3K2R b KQkq a3 0 1 | -112 | 0.0
+
+8/8/8/8/8/8/8/K1k5 b - - 12 70 | 0 | 1-0
+";
+        let mut packed = Vec::new();
+        assert_eq!(text_to_packed(text.as_bytes(), &mut packed).unwrap(), 3);
+        assert_eq!(packed.len(), 3 * PACKED_SIZE);
+
+        let mut output = Vec::new();
+        assert_eq!(packed_to_text(&packed[..], &mut output).unwrap(), 3);
+        assert_eq!(
+            String::from_utf8(output).unwrap(),
+            "\
+rnbqkbnr/pppppppp/8/8/8/8/PPPPPPPP/RNBQKBNR w KQkq - 0 1 | 25 | 0.5
+r3k2r/p1ppqpb1/bn2pnp1/3PN3/Pp2P3/2N2Q1p/1PPBBPPP/R3K2R b KQkq a3 0 1 | -112 | 0.0
+8/8/8/8/8/8/8/K1k5 b - - 12 70 | 0 | 1.0
+"
+        );
+
+        assert!(matches!(
+            TrainingEntry::from_text("8/8/8/8/8/8/8/K1k5 b - - 12 70 | 0"),
+            Err(DataError::Invalid(_))
+        ));
+    }
+}
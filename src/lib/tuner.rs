@@ -0,0 +1,415 @@
+use crate::board::Board;
+use crate::definitions::*;
+use crate::endgame;
+use crate::evaluation::{self, Score, MAX_PHASE};
+use crate::fen;
+use crate::trace::{Param, Trace};
+
+use std::io::{BufRead, Write};
+use strum::{EnumCount, IntoEnumIterator};
+
+/// Parse a labelled position. Two formats are accepted: a FEN followed by the
+/// game result, either bare or in brackets, e.g. `... w - - 0 1 [1-0]` or
+/// `... w - - 0 1 0.5`, and an EPD with the result in the `c9` opcode, e.g.
+/// `... w - - c9 "1/2-1/2";`. The result is from white's point of view, 1 for a
+/// win, 0.5 for a draw and 0 for a loss.
+pub fn parse_position(line: &str) -> Result<(Board, f64), String> {
+    let fields: Vec<&str> = line.split_whitespace().collect();
+
+    let (position, result) = match fields.iter().position(|&f| f == "c9") {
+        Some(c9) if c9 >= 4 => {
+            let result = fields
+                .get(c9 + 1)
+                .ok_or_else(|| format!("Missing c9 result in {line}"))?;
+            (fields[..4].to_vec(), *result)
+        }
+        _ if fields.len() >= 5 => (
+            fields[..fields.len() - 1].to_vec(),
+            fields[fields.len() - 1],
+        ),
+        _ => return Err(format!("Invalid position {line}")),
+    };
+
+    let result = parse_result(result).ok_or_else(|| format!("Invalid result in {line}"))?;
+
+    // EPD has no move counters
+    let fen = match position.len() {
+        4 => format!("{} 0 1", position.join(" ")),
+        6 => position.join(" "),
+        _ => return Err(format!("Invalid position {line}")),
+    };
+
+    let board = fen::fen_to_board(&fen)?;
+    if !board.has_one_king_each() {
+        return Err(format!("Missing or extra king in {line}"));
+    }
+
+    Ok((board, result))
+}
+
+fn parse_result(s: &str) -> Option<f64> {
+    match s.trim_matches(|c| matches!(c, '[' | ']' | '"' | ';')) {
+        "1-0" => Some(1.0),
+        "0-1" => Some(0.0),
+        "1/2-1/2" => Some(0.5),
+        s => s.parse().ok().filter(|r| (0.0..=1.0).contains(r)),
+    }
+}
+
+/// The number of individual parameters, counting every entry of every table.
+pub fn param_count() -> usize {
+    Param::iter().map(|p| p.values().len()).sum()
+}
+
+// Where each parameter's entries start in the flattened parameter list
+fn param_offsets() -> [usize; Param::COUNT] {
+    let mut offsets = [0; Param::COUNT];
+    let mut offset = 0;
+    for param in Param::iter() {
+        offsets[param as usize] = offset;
+        offset += param.values().len();
+    }
+    offsets
+}
+
+// The evaluation is linear in its parameters, so tracing how many times each
+// one is used, white's uses minus black's, gives its coefficient
+struct Coefficients {
+    offsets: [usize; Param::COUNT],
+    counts: Vec<i32>,
+}
+
+impl Trace for Coefficients {
+    fn add(&mut self, param: Param, index: usize, side: Side, count: i32) {
+        let i = self.offsets[param as usize] + index;
+        match side {
+            Side::White => self.counts[i] += count,
+            Side::Black => self.counts[i] -= count,
+        }
+    }
+}
+
+/// A labelled position reduced to the coefficient of every parameter.
+#[derive(Clone, Debug)]
+pub struct TuningEntry {
+    /// The parameters used in the position and their coefficients.
+    pub coefficients: Vec<(usize, i32)>,
+    pub phase: i32,
+    /// The endgame scale factor, taken as fixed while tuning.
+    pub scale: i32,
+    pub result: f64,
+}
+
+impl TuningEntry {
+    /// Returns `None` for positions that aren't evaluated by the general
+    /// evaluation, i.e. known endgames and dead draws.
+    pub fn new(board: &Board, result: f64) -> Option<TuningEntry> {
+        if endgame::insufficient_material(board)
+            || endgame::endgames()
+                .probe(endgame::material_key(board))
+                .is_some()
+        {
+            return None;
+        }
+
+        let mut coefficients = Coefficients {
+            offsets: param_offsets(),
+            counts: vec![0; param_count()],
+        };
+        let score = evaluation::trace_terms(board, &mut coefficients);
+
+        Some(TuningEntry {
+            coefficients: coefficients
+                .counts
+                .iter()
+                .enumerate()
+                .filter(|(_, &c)| c != 0)
+                .map(|(i, &c)| (i, c))
+                .collect(),
+            phase: evaluation::phase(board),
+            scale: evaluation::scale_factor(board, score),
+            result,
+        })
+    }
+}
+
+/// Read labelled positions, one per line, skipping positions the general
+/// evaluation doesn't handle.
+pub fn load_entries<R: BufRead>(reader: R) -> Result<Vec<TuningEntry>, String> {
+    let mut entries = Vec::new();
+    for line in reader.lines() {
+        let line = line.map_err(|e| e.to_string())?;
+        if line.trim().is_empty() {
+            continue;
+        }
+
+        let (board, result) = parse_position(&line)?;
+        entries.extend(TuningEntry::new(&board, result));
+    }
+    Ok(entries)
+}
+
+const ADAM_BETA1: f64 = 0.9;
+const ADAM_BETA2: f64 = 0.999;
+const ADAM_EPSILON: f64 = 1e-8;
+
+/// Tune the evaluation parameters by minimising the error between the
+/// predicted and actual results of labelled positions, as in
+/// <https://www.chessprogramming.org/Texel%27s_Tuning_Method>. The prediction
+/// is `sigmoid(K * eval)` with the evaluation linearised over the parameters.
+pub struct Tuner {
+    entries: Vec<TuningEntry>,
+    /// The middlegame and endgame value of every parameter.
+    params: Vec<[f64; 2]>,
+    k: f64,
+    // Adam moment estimates
+    momentum: Vec<[f64; 2]>,
+    velocity: Vec<[f64; 2]>,
+    steps: i32,
+}
+
+impl Tuner {
+    /// Start from the current values of the parameters.
+    pub fn new(entries: Vec<TuningEntry>) -> Tuner {
+        let params: Vec<[f64; 2]> = Param::iter()
+            .flat_map(|p| p.values().iter())
+            .map(|s| [s.mg() as f64, s.eg() as f64])
+            .collect();
+        let count = params.len();
+
+        Tuner {
+            entries,
+            params,
+            k: 1.0,
+            momentum: vec![[0.0; 2]; count],
+            velocity: vec![[0.0; 2]; count],
+            steps: 0,
+        }
+    }
+
+    pub const fn k(&self) -> f64 {
+        self.k
+    }
+
+    // The evaluation of an entry from white's point of view, in centipawns
+    fn evaluate(&self, entry: &TuningEntry) -> f64 {
+        let (mut mg, mut eg) = (0.0, 0.0);
+        for &(i, c) in &entry.coefficients {
+            mg += self.params[i][0] * c as f64;
+            eg += self.params[i][1] * c as f64;
+        }
+        let phase = entry.phase as f64 / MAX_PHASE as f64;
+        let scale = entry.scale as f64 / endgame::SCALE_NORMAL as f64;
+        mg * phase + eg * scale * (1.0 - phase)
+    }
+
+    fn sigmoid(k: f64, eval: f64) -> f64 {
+        1.0 / (1.0 + 10f64.powf(-k * eval / 400.0))
+    }
+
+    /// The mean squared error of the predicted results.
+    pub fn error(&self, k: f64) -> f64 {
+        let total: f64 = self
+            .entries
+            .iter()
+            .map(|e| (e.result - Tuner::sigmoid(k, self.evaluate(e))).powi(2))
+            .sum();
+        total / self.entries.len() as f64
+    }
+
+    /// Find the scaling constant K that minimises the error with the current
+    /// parameters, by golden section search. The error has a single minimum in K.
+    pub fn fit_k(&mut self) -> f64 {
+        let ratio = (5f64.sqrt() - 1.0) / 2.0;
+        let (mut lo, mut hi) = (0.0, 10.0);
+
+        while hi - lo > 1e-6 {
+            let a = hi - ratio * (hi - lo);
+            let b = lo + ratio * (hi - lo);
+            if self.error(a) < self.error(b) {
+                hi = b;
+            } else {
+                lo = a;
+            }
+        }
+
+        self.k = (lo + hi) / 2.0;
+        self.k
+    }
+
+    /// Take one step of Adam over the whole data set.
+    pub fn step(&mut self, learning_rate: f64) {
+        let mut gradient = vec![[0.0; 2]; self.params.len()];
+
+        for entry in &self.entries {
+            let s = Tuner::sigmoid(self.k, self.evaluate(entry));
+            // derivative of the squared error with respect to the evaluation
+            let d = 2.0 * (s - entry.result) * s * (1.0 - s) * self.k * 10f64.ln() / 400.0;
+
+            let phase = entry.phase as f64 / MAX_PHASE as f64;
+            let scale = entry.scale as f64 / endgame::SCALE_NORMAL as f64;
+            for &(i, c) in &entry.coefficients {
+                gradient[i][0] += d * c as f64 * phase;
+                gradient[i][1] += d * c as f64 * scale * (1.0 - phase);
+            }
+        }
+
+        self.steps += 1;
+        let n = self.entries.len() as f64;
+        let params = self
+            .params
+            .iter_mut()
+            .flatten()
+            .zip(self.momentum.iter_mut().flatten())
+            .zip(self.velocity.iter_mut().flatten())
+            .zip(gradient.iter().flatten());
+        for (((param, momentum), velocity), g) in params {
+            let g = g / n;
+            *momentum = ADAM_BETA1 * *momentum + (1.0 - ADAM_BETA1) * g;
+            *velocity = ADAM_BETA2 * *velocity + (1.0 - ADAM_BETA2) * g * g;
+
+            let m = *momentum / (1.0 - ADAM_BETA1.powi(self.steps));
+            let v = *velocity / (1.0 - ADAM_BETA2.powi(self.steps));
+            *param -= learning_rate * m / (v.sqrt() + ADAM_EPSILON);
+        }
+    }
+
+    /// The current parameters, rounded.
+    pub fn scores(&self) -> Vec<Score> {
+        self.params
+            .iter()
+            .map(|[mg, eg]| Score(mg.round() as i32, eg.round() as i32))
+            .collect()
+    }
+
+    /// Write the parameters as Rust constants, to be pasted over the tables in
+    /// the evaluation.
+    pub fn write_source<W: Write>(&self, out: &mut W) -> std::io::Result<()> {
+        let scores = self.scores();
+        let offsets = param_offsets();
+
+        for param in Param::iter() {
+            let start = offsets[param as usize];
+            let values = &scores[start..start + param.values().len()];
+
+            if let [value] = values {
+                writeln!(
+                    out,
+                    "pub const {}: Score = Score({}, {});",
+                    param.name(),
+                    value.mg(),
+                    value.eg()
+                )?;
+            } else {
+                writeln!(
+                    out,
+                    "pub const {}: [Score; {}] = [",
+                    param.name(),
+                    values.len()
+                )?;
+                for value in values {
+                    writeln!(out, "    Score({}, {}),", value.mg(), value.eg())?;
+                }
+                writeln!(out, "];")?;
+            }
+        }
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::{evaluation::taper, tuner::*};
+
+    #[test]
+    fn parse_formats() {
+        let start = "rnbqkbnr/pppppppp/8/8/8/8/PPPPPPPP/RNBQKBNR w KQkq - 0 1";
+        for (line, expected) in [
+            (format!("{start} [1-0]"), 1.0),
+            (format!("{start} [0.5]"), 0.5),
+            (format!("{start} 0-1"), 0.0),
+            (format!("{start} 1/2-1/2"), 0.5),
+            (format!("{} c9 \"1-0\";", &start[..start.len() - 4]), 1.0),
+            (
+                format!("{} id \"start\"; c9 \"0-1\";", &start[..start.len() - 4]),
+                0.0,
+            ),
+        ] {
+            let (board, result) = parse_position(&line).unwrap();
+            assert_eq!(result, expected, "{line}");
+            assert_eq!(fen::board_to_fen(&board), start);
+        }
+
+        assert!(parse_position(&format!("{start} [2.0]")).is_err());
+        assert!(parse_position("8/8/8/8 w [1-0]").is_err());
+        assert!(parse_position("8/8/8/8/8/8/8/K7 w - - 0 1 [1-0]").is_err());
+    }
+
+    // The linearised evaluation must agree with the real one, apart from the
+    // rounding when tapering
+    #[test]
+    fn coefficients_reproduce_evaluation() {
+        let tuner = Tuner::new(Vec::new());
+
+        for fen in [
+            "r3k2r/p1ppqpb1/bn2pnp1/3PN3/1p2P3/2N2Q1p/PPPBBPPP/R3K2R w KQkq - 0 1",
+            "8/2p5/3p4/KP5r/1R3p1k/8/4P1P1/8 w - - 0 1",
+            "6k1/6p1/6Pp/ppp5/3pn2P/1P3K2/1PP2P2/3N4 b - - 0 1",
+            "8/4kb2/8/5p2/4P3/8/3BK3/8 b - - 0 1",
+        ] {
+            let board = fen::fen_to_board(fen).unwrap();
+            let entry = TuningEntry::new(&board, 0.5).unwrap();
+
+            let trace = evaluation::eval_trace(&board).unwrap();
+            assert!(
+                (tuner.evaluate(&entry) - trace.eval as f64).abs() <= 1.0,
+                "{fen}"
+            );
+
+            let score = trace.total();
+            let scaled = Score(score.mg(), score.eg() * entry.scale / endgame::SCALE_NORMAL);
+            assert_eq!(taper(scaled, entry.phase), trace.eval, "{fen}");
+        }
+
+        // known endgames are not tuned
+        let board = fen::fen_to_board("8/8/8/3k4/8/8/8/KBN5 w - - 0 1").unwrap();
+        assert!(TuningEntry::new(&board, 1.0).is_none());
+    }
+
+    #[test]
+    fn tuning_reduces_error() {
+        // a won position recorded as lost and the other way round, the
+        // tuner has to move the parameters to reduce the error
+        let data = "\
+r3k2r/p1ppqpb1/bn2pnp1/3PN3/1p2P3/2N2Q1p/PPPBBPPP/R3K2R w KQkq - 0 1 [0.5]
+4k3/pp6/8/8/8/8/PPP5/R3K3 w - - 0 1 [0.0]
+4k3/pp6/8/8/8/8/PPP5/R3K3 b - - 0 1 [0.0]
+r3k3/pp6/8/8/8/8/PPP5/4K3 w - - 0 1 [1-0]
+8/2p5/3p4/KP5r/1R3p1k/8/4P1P1/8 w - - c9 \"1/2-1/2\";
+";
+        let entries = load_entries(data.as_bytes()).unwrap();
+        assert_eq!(entries.len(), 5);
+
+        let mut tuner = Tuner::new(entries);
+        let k = tuner.fit_k();
+        let initial = tuner.error(k);
+        for _ in 0..50 {
+            tuner.step(1.0);
+        }
+        assert!(tuner.error(k) < initial);
+    }
+
+    #[test]
+    fn writes_source() {
+        let tuner = Tuner::new(Vec::new());
+        let mut out = Vec::new();
+        tuner.write_source(&mut out).unwrap();
+        let source = String::from_utf8(out).unwrap();
+
+        assert!(source.contains("pub const ISOLATED_PAWN: Score = Score(-8, -12);\n"));
+        assert!(source.contains(
+            "pub const PIECE_VALUES: [Score; 6] = [\n    Score(82, 94),\n    Score(337, 281),\n"
+        ));
+        assert_eq!(source.matches("pub const").count(), Param::COUNT);
+    }
+}
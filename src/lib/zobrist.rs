@@ -0,0 +1,173 @@
+use crate::board::Board;
+use crate::definitions::*;
+
+use strum::{EnumCount, IntoEnumIterator};
+
+// https://prng.di.unimi.it/splitmix64.c
+const fn splitmix64(state: u64) -> (u64, u64) {
+    let state = state.wrapping_add(0x9e3779b97f4a7c15);
+    let mut z = state;
+    z = (z ^ (z >> 30)).wrapping_mul(0xbf58476d1ce4e5b9);
+    z = (z ^ (z >> 27)).wrapping_mul(0x94d049bb133111eb);
+    (state, z ^ (z >> 31))
+}
+
+/// A precomputed array of random keys for each piece on each square.
+/// the first index is the piece, the second index is the square.
+pub const PIECE_SQUARE_KEYS: [[u64; Square::COUNT]; Piece::COUNT] = {
+    let mut keys = [[0; Square::COUNT]; Piece::COUNT];
+    let mut state = 0x4f78_6964_6521;
+    let mut i = 0;
+    while i < Piece::COUNT {
+        let mut j = 0;
+        while j < Square::COUNT {
+            let (next, key) = splitmix64(state);
+            state = next;
+            keys[i][j] = key;
+            j += 1;
+        }
+        i += 1;
+    }
+    keys
+};
+
+/// Keys for the rest of the position: the side to move, each castling right
+/// (KQkq) and the file of the en passant square.
+pub const SIDE_KEY: u64 = STATE_KEYS[0];
+pub const CASTLING_KEYS: [u64; 4] = [STATE_KEYS[1], STATE_KEYS[2], STATE_KEYS[3], STATE_KEYS[4]];
+pub const EN_PASSANT_KEYS: [u64; 8] = {
+    let mut keys = [0; 8];
+    let mut i = 0;
+    while i < 8 {
+        keys[i] = STATE_KEYS[5 + i];
+        i += 1;
+    }
+    keys
+};
+
+const STATE_KEYS: [u64; 13] = {
+    let mut keys = [0; 13];
+    let mut state = 0x4f78_6964_6522;
+    let mut i = 0;
+    while i < keys.len() {
+        let (next, key) = splitmix64(state);
+        state = next;
+        keys[i] = key;
+        i += 1;
+    }
+    keys
+};
+
+/// The key of everything but the piece placement, which `Board` keeps
+/// incrementally.
+pub fn state_key(board: &Board) -> u64 {
+    let mut key = 0;
+    if board.stm == Side::Black {
+        key ^= SIDE_KEY;
+    }
+
+    let castling = [
+        board.white_king_castle,
+        board.white_queen_castle,
+        board.black_king_castle,
+        board.black_queen_castle,
+    ];
+    for (right, castling_key) in castling.iter().zip(CASTLING_KEYS) {
+        if *right {
+            key ^= castling_key;
+        }
+    }
+
+    if let Some(sq) = board.en_passant {
+        key ^= EN_PASSANT_KEYS[sq as usize % 8];
+    }
+    key
+}
+
+/// Calculate the full key of the board from scratch.
+pub fn key(board: &Board) -> u64 {
+    let mut key = state_key(board);
+    for piece in Piece::iter() {
+        let mut pieces = board.get_piece_bb(piece);
+        while pieces != BB_EMPTY {
+            key ^= PIECE_SQUARE_KEYS[piece as usize][pieces.poplsb() as usize];
+        }
+    }
+    key
+}
+
+/// Calculate the pawn key of the board from scratch. `Board` keeps this up to
+/// date incrementally, this is useful for checking that it does so correctly.
+pub fn pawn_key(board: &Board) -> u64 {
+    let mut key = 0;
+    for piece in [Piece::WhitePawn, Piece::BlackPawn] {
+        let mut pawns = board.get_piece_bb(piece);
+        while pawns != BB_EMPTY {
+            key ^= PIECE_SQUARE_KEYS[piece as usize][pawns.poplsb() as usize];
+        }
+    }
+    key
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::{fen, generate_moves, zobrist::*};
+
+    fn check_keys(board: &Board, depth: u32) {
+        assert_eq!(board.pawn_key(), pawn_key(board));
+        assert_eq!(board.key(), key(board));
+
+        if depth == 0 {
+            return;
+        }
+
+        let mut moves = Vec::new();
+        generate_moves::legal_moves(board, &mut moves);
+        for m in moves {
+            let mut board = *board;
+            board.make_move(&m);
+            check_keys(&board, depth - 1);
+        }
+    }
+
+    #[test]
+    fn incremental_keys() {
+        for fen in [
+            "rnbqkbnr/pppppppp/8/8/8/8/PPPPPPPP/RNBQKBNR w KQkq - 0 1",
+            "r3k2r/p1ppqpb1/bn2pnp1/3PN3/1p2P3/2N2Q1p/PPPBBPPP/R3K2R w KQkq - 0 1",
+            "n1n5/PPPk4/8/8/8/8/4Kppp/5N1N b - - 0 1",
+            "8/2p5/3p4/KP5r/1R3p1k/8/4P1P1/8 w - - 0 1",
+        ] {
+            check_keys(&fen::fen_to_board(fen).unwrap(), 3);
+        }
+    }
+
+    #[test]
+    fn state_changes_key() {
+        let board = fen::fen_to_board("r3k2r/8/8/3pP3/8/8/8/R3K2R w KQkq d6 0 1").unwrap();
+        let mut keys = vec![board.key()];
+        for fen in [
+            "r3k2r/8/8/3pP3/8/8/8/R3K2R b KQkq d6 0 1",
+            "r3k2r/8/8/3pP3/8/8/8/R3K2R w Qkq d6 0 1",
+            "r3k2r/8/8/3pP3/8/8/8/R3K2R w KQk d6 0 1",
+            "r3k2r/8/8/3pP3/8/8/8/R3K2R w KQkq - 0 1",
+            // the clocks aren't part of the key
+            "r3k2r/8/8/3pP3/8/8/8/R3K2R w KQkq d6 5 20",
+        ] {
+            keys.push(fen::fen_to_board(fen).unwrap().key());
+        }
+        assert_eq!(keys[5], keys[0]);
+        keys.sort_unstable();
+        keys.dedup();
+        assert_eq!(keys.len(), 5);
+    }
+
+    #[test]
+    fn keys_are_unique() {
+        let mut keys: Vec<u64> = PIECE_SQUARE_KEYS.iter().flatten().copied().collect();
+        keys.extend_from_slice(&STATE_KEYS);
+        keys.sort_unstable();
+        keys.dedup();
+        assert_eq!(keys.len(), Piece::COUNT * Square::COUNT + STATE_KEYS.len());
+    }
+}
@@ -0,0 +1,54 @@
+use std::process::{Command, Output};
+
+fn oxide(args: &[&str]) -> Output {
+    Command::new(env!("CARGO_BIN_EXE_oxide"))
+        .args(args)
+        .output()
+        .unwrap()
+}
+
+#[test]
+fn kingless_positions_are_rejected() {
+    for args in [
+        ["eval", "8/8/8/8/8/8/8/8 w - - 0 1"].as_slice(),
+        &["eval", "8/8/8/8/8/8/8/K7 w - - 0 1"],
+        &["perft", "divide", "2", "8/8/8/8/8/8/8/8 w - - 0 1"],
+        &["mate", "2", "k7/8/8/8/8/8/8/KK6 w - - 0 1"],
+    ] {
+        let output = oxide(args);
+        assert_eq!(output.status.code(), Some(2), "{args:?}");
+        let stderr = String::from_utf8(output.stderr).unwrap();
+        assert!(stderr.contains("Missing or extra king"), "{args:?}: {stderr}");
+    }
+}
+
+#[test]
+fn eval_prints_the_trace() {
+    let output = oxide(&["eval"]);
+    assert!(output.status.success());
+    let stdout = String::from_utf8(output.stdout).unwrap();
+    assert!(stdout.contains("Final evaluation"), "{stdout}");
+}
+
+#[test]
+fn kingless_training_data_is_rejected() {
+    let dir = std::env::temp_dir().join(format!("oxide-cli-{}", std::process::id()));
+    std::fs::create_dir_all(&dir).unwrap();
+    let text = dir.join("positions.txt");
+    std::fs::write(&text, "8/8/8/8/8/8/8/K7 w - - 0 1 | 0 | 0.5\n").unwrap();
+    let epd = dir.join("positions.epd");
+    std::fs::write(&epd, "8/8/8/8/8/8/8/K7 w - - [0.5]\n").unwrap();
+
+    let packed = dir.join("positions.bin");
+    for args in [
+        ["pack", text.to_str().unwrap(), packed.to_str().unwrap()].as_slice(),
+        &["tune", epd.to_str().unwrap()],
+    ] {
+        let output = oxide(args);
+        assert_eq!(output.status.code(), Some(2), "{args:?}");
+        let stderr = String::from_utf8(output.stderr).unwrap();
+        assert!(stderr.contains("Missing or extra king"), "{args:?}: {stderr}");
+    }
+
+    std::fs::remove_dir_all(&dir).unwrap();
+}